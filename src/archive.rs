@@ -0,0 +1,405 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use sevenz_rust::{Archive, BlockDecoder, SevenZArchiveEntry};
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek};
+use std::path::Path;
+use std::time::SystemTime;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::{
+    is_supported_file_name, new_file_details, process_embedded_file, redact_nhs_numbers,
+    ArchiveMetadata, ScanOptions,
+};
+
+/// Tracks how many decompressed bytes may still be read while scanning one top-level file.
+/// Shared by nested archives so that a zip bomb cannot multiply the limit by nesting.
+/// Bytes decompressed only to skip unsupported members of sequential formats are counted
+/// against a separate allowance of the same size, so they never crowd out supported members.
+pub(crate) struct ArchiveBudget {
+    remaining_bytes: u64,
+    remaining_skip_bytes: u64,
+}
+
+impl ArchiveBudget {
+    pub(crate) fn new(limit: u64) -> Self {
+        ArchiveBudget {
+            remaining_bytes: limit,
+            remaining_skip_bytes: limit,
+        }
+    }
+
+    /// Reserves `size` bytes, returning `false` if that would exceed the limit.
+    pub(crate) fn reserve(&mut self, size: u64) -> bool {
+        if size > self.remaining_bytes {
+            self.remaining_bytes = 0;
+            false
+        } else {
+            self.remaining_bytes -= size;
+            true
+        }
+    }
+
+    /// Reserves `size` bytes of the skip allowance, returning `false` if that would exceed it.
+    fn reserve_skip(&mut self, size: u64) -> bool {
+        if size > self.remaining_skip_bytes {
+            self.remaining_skip_bytes = 0;
+            false
+        } else {
+            self.remaining_skip_bytes -= size;
+            true
+        }
+    }
+}
+
+/// Returns the archive format for a file name, or `None` if it is not a supported archive.
+pub(crate) fn archive_format(name: &str) -> Option<&'static str> {
+    let lower = name.to_lowercase();
+    if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        Some("tar.gz")
+    } else if lower.ends_with(".tar") {
+        Some("tar")
+    } else if lower.ends_with(".zip") {
+        Some("zip")
+    } else if lower.ends_with(".7z") {
+        Some("7z")
+    } else {
+        None
+    }
+}
+
+pub(crate) fn extract_archive_metadata(
+    path: &Path,
+    format: &str,
+    options: &ScanOptions,
+    budget: &mut ArchiveBudget,
+) -> Result<ArchiveMetadata> {
+    let mut file = File::open(path)?;
+    let mut scan = MemberScan::new(format, options, 0, budget);
+
+    match format {
+        "zip" => scan.scan_zip(ZipArchive::new(file)?)?,
+        "tar" => scan.scan_tar(tar::Archive::new(file))?,
+        "tar.gz" => scan.scan_tar(tar::Archive::new(GzDecoder::new(file)))?,
+        "7z" => {
+            let len = file.metadata()?.len();
+            scan.scan_7z(&mut file, len)?
+        }
+        _ => {}
+    }
+
+    Ok(scan.metadata)
+}
+
+pub(crate) fn extract_archive_metadata_from_bytes(
+    bytes: &[u8],
+    format: &str,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> Result<ArchiveMetadata> {
+    if depth >= options.archive_max_depth {
+        let mut metadata = empty_metadata(format);
+        metadata.limit_reached = Some("depth".to_string());
+        return Ok(metadata);
+    }
+
+    let mut scan = MemberScan::new(format, options, depth, budget);
+
+    match format {
+        "zip" => scan.scan_zip(ZipArchive::new(Cursor::new(bytes))?)?,
+        "tar" => scan.scan_tar(tar::Archive::new(bytes))?,
+        "tar.gz" => scan.scan_tar(tar::Archive::new(GzDecoder::new(bytes)))?,
+        "7z" => scan.scan_7z(&mut Cursor::new(bytes), bytes.len() as u64)?,
+        _ => {}
+    }
+
+    Ok(scan.metadata)
+}
+
+fn empty_metadata(format: &str) -> ArchiveMetadata {
+    ArchiveMetadata {
+        archive_format: format.to_string(),
+        member_count: 0,
+        total_uncompressed_size: 0,
+        members: Vec::new(),
+        limit_reached: None,
+    }
+}
+
+/// Walks the members of one archive, running supported members through the extractors.
+struct MemberScan<'a> {
+    options: &'a ScanOptions<'a>,
+    depth: usize,
+    budget: &'a mut ArchiveBudget,
+    metadata: ArchiveMetadata,
+}
+
+impl<'a> MemberScan<'a> {
+    fn new(format: &str, options: &'a ScanOptions<'a>, depth: usize, budget: &'a mut ArchiveBudget) -> Self {
+        MemberScan {
+            options,
+            depth,
+            budget,
+            metadata: empty_metadata(format),
+        }
+    }
+
+    fn scan_zip<R: Read + Seek>(&mut self, mut archive: ZipArchive<R>) -> Result<()> {
+        for index in 0..archive.len() {
+            let (name, size, created) = {
+                let raw = archive.by_index_raw(index)?;
+                if raw.is_dir() {
+                    continue;
+                }
+                (raw.name().to_string(), raw.size(), zip_time(&raw.last_modified()))
+            };
+
+            // Zip members can be read individually, so unsupported members are never decompressed
+            if !is_supported_file_name(&name, self.options) {
+                self.count_member(size);
+                continue;
+            }
+
+            match archive.by_index(index) {
+                Ok(mut member) => {
+                    if !self.add_member(&name, size, &created, &mut member) {
+                        break;
+                    }
+                }
+                Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                    self.count_member(size);
+                    let mut details = new_file_details(redact_nhs_numbers(&name), created, Some(size), None);
+                    details.encrypted = Some(true);
+                    details.protection_type = Some("password".to_string());
+                    self.metadata.members.push(details);
+                }
+                Err(_) => self.count_member(size),
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_tar<R: Read>(&mut self, mut archive: tar::Archive<R>) -> Result<()> {
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let name = entry.path()?.to_string_lossy().to_string();
+            let size = entry.size();
+            let created = entry
+                .header()
+                .mtime()
+                .ok()
+                .and_then(|mtime| DateTime::<Utc>::from_timestamp(mtime as i64, 0))
+                .map(|datetime| datetime.format("%Y-%m-%dT%H:%M").to_string())
+                .unwrap_or_default();
+
+            let scanning = if is_supported_file_name(&name, self.options) {
+                self.add_member(&name, size, &created, &mut entry)
+            } else {
+                self.skip_member(size, &mut entry)
+            };
+            if !scanning {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_7z<R: Read + Seek>(&mut self, source: &mut R, len: u64) -> Result<()> {
+        let archive = Archive::read(source, len, &[])?;
+
+        for folder_index in 0..archive.folders.len() {
+            let decoder = BlockDecoder::new(folder_index, &archive, &[], source);
+
+            // Folders are compressed as one stream, so one without supported members is never
+            // decompressed, while unsupported members of any other folder have to be read past
+            let any_supported = decoder
+                .entries()
+                .iter()
+                .any(|entry| !entry.is_directory() && is_supported_file_name(entry.name(), self.options));
+            if !any_supported {
+                for entry in decoder.entries().iter().filter(|entry| !entry.is_directory()) {
+                    self.count_member(entry.size());
+                }
+                continue;
+            }
+
+            let scanning = decoder.for_each_entries(&mut |entry, member| {
+                if entry.is_directory() {
+                    return Ok(true);
+                }
+                if !is_supported_file_name(entry.name(), self.options) {
+                    return Ok(self.skip_member(entry.size(), member));
+                }
+                let created = sevenz_time(entry);
+                Ok(self.add_member(entry.name(), entry.size(), &created, member))
+            })?;
+            if !scanning {
+                return Ok(());
+            }
+        }
+
+        // Empty files are stored outside any folder
+        for (file_index, entry) in archive.files.iter().enumerate() {
+            if archive.stream_map.file_folder_index[file_index].is_some() || entry.is_directory() {
+                continue;
+            }
+            if !is_supported_file_name(entry.name(), self.options) {
+                self.count_member(0);
+                continue;
+            }
+            let created = sevenz_time(entry);
+            if !self.add_member(entry.name(), 0, &created, &mut io::empty()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn count_member(&mut self, size: u64) {
+        self.metadata.member_count += 1;
+        self.metadata.total_uncompressed_size += size;
+    }
+
+    /// Decompresses one supported member against the budget.
+    /// Returns `false` once the size limit is reached and scanning should stop.
+    fn add_member<R: Read + ?Sized>(&mut self, name: &str, size: u64, created: &str, reader: &mut R) -> bool {
+        self.count_member(size);
+
+        if !self.budget.reserve(size) {
+            self.metadata.limit_reached = Some("total_size".to_string());
+            return false;
+        }
+
+        // Never read past the declared size, whatever the compressed stream claims
+        let mut limited = reader.take(size);
+        let mut bytes = Vec::new();
+        if limited.read_to_end(&mut bytes).is_err() {
+            return true;
+        }
+
+        let details = process_embedded_file(name, &bytes, created, self.options, self.depth + 1, self.budget);
+        // Text members that turn out not to be tabular are left out, as they are on disk
+        if details.file_type.is_some() {
+            self.metadata.members.push(details);
+        }
+        true
+    }
+
+    /// Reads past an unsupported member of a sequential format (tar, or a 7z folder shared
+    /// with supported members), which has to be decompressed to reach the next member.
+    /// Returns `false` once the skip allowance is used up and scanning should stop.
+    fn skip_member<R: Read + ?Sized>(&mut self, size: u64, reader: &mut R) -> bool {
+        self.count_member(size);
+
+        if !self.budget.reserve_skip(size) {
+            self.metadata.limit_reached = Some("total_size".to_string());
+            return false;
+        }
+
+        io::copy(&mut reader.take(size), &mut io::sink()).is_ok()
+    }
+}
+
+fn sevenz_time(entry: &SevenZArchiveEntry) -> String {
+    let created: DateTime<Utc> = SystemTime::from(entry.last_modified_date()).into();
+    created.format("%Y-%m-%dT%H:%M").to_string()
+}
+
+pub(crate) fn zip_time(datetime: &zip::DateTime) -> String {
+    NaiveDate::from_ymd_opt(
+        datetime.year() as i32,
+        datetime.month() as u32,
+        datetime.day() as u32,
+    )
+    .and_then(|date| date.and_hms_opt(datetime.hour() as u32, datetime.minute() as u32, 0))
+    .map(|datetime| datetime.format("%Y-%m-%dT%H:%M").to_string())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{content, PersonNameMode};
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    const CSV: &[u8] = b"id,name\n1,alpha\n2,beta\n3,gamma\n4,delta\n";
+
+    fn options(limit: u64) -> ScanOptions<'static> {
+        ScanOptions {
+            enable_hash: false,
+            max_rows: usize::MAX,
+            fingerprints: content::Fingerprints::default(),
+            person_names: PersonNameMode::Keep,
+            scan_archives: true,
+            archive_max_depth: 3,
+            archive_max_total_size: limit,
+        }
+    }
+
+    fn tar_of(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn zip_of(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in members {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn scan(bytes: &[u8], format: &str, limit: u64) -> ArchiveMetadata {
+        let options = options(limit);
+        let mut budget = ArchiveBudget::new(limit);
+        extract_archive_metadata_from_bytes(bytes, format, &options, 0, &mut budget).unwrap()
+    }
+
+    #[test]
+    fn unsupported_members_do_not_use_the_budget() {
+        let blob = vec![7u8; 2 * CSV.len()];
+        let limit = 2 * CSV.len() as u64;
+        let members: &[(&str, &[u8])] = &[("blob.bin", &blob), ("data.csv", CSV)];
+
+        for (bytes, format) in [(zip_of(members), "zip"), (tar_of(members), "tar")] {
+            let metadata = scan(&bytes, format, limit);
+            assert_eq!(metadata.member_count, 2, "{format}");
+            assert_eq!(metadata.total_uncompressed_size, (blob.len() + CSV.len()) as u64, "{format}");
+            assert_eq!(metadata.limit_reached, None, "{format}");
+            assert_eq!(metadata.members.len(), 1, "{format}");
+            assert_eq!(metadata.members[0].name, "data.csv", "{format}");
+        }
+    }
+
+    #[test]
+    fn tar_skips_have_their_own_limit() {
+        let blob = vec![7u8; CSV.len()];
+        let limit = CSV.len() as u64 + 1;
+        let members: &[(&str, &[u8])] = &[("a.bin", &blob), ("b.bin", &blob), ("data.csv", CSV)];
+
+        let metadata = scan(&tar_of(members), "tar", limit);
+        assert_eq!(metadata.limit_reached.as_deref(), Some("total_size"));
+        assert!(metadata.members.is_empty());
+
+        // Zip members are skipped without being read, so no limit applies
+        let metadata = scan(&zip_of(members), "zip", limit);
+        assert_eq!(metadata.limit_reached, None);
+        assert_eq!(metadata.members.len(), 1);
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::Result;
+use parquet::basic::ConvertedType;
+use parquet::file::metadata::ParquetMetaDataReader;
+use serde_json::Value;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{calculate_column_similarity_hash, redact_nhs_numbers, ColumnarMetadata};
+
+const PARQUET_MAGIC: &[u8; 4] = b"PAR1";
+const ARROW_MAGIC: &[u8; 6] = b"ARROW1";
+const AVRO_MAGIC: &[u8; 4] = b"Obj\x01";
+
+// Parquet ends with a 4-byte footer length followed by the magic
+const PARQUET_TAIL_LEN: u64 = 8;
+// Arrow IPC files end with a 4-byte footer length followed by the 6-byte magic
+const ARROW_TAIL_LEN: u64 = 10;
+// Encapsulated IPC messages start with this marker before their length (since Arrow 0.15)
+const ARROW_CONTINUATION: u32 = 0xFFFF_FFFF;
+const AVRO_SYNC_LEN: i64 = 16;
+
+// Footers and headers are small; anything larger than this is a corrupt length field
+const MAX_FOOTER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Returns the columnar format for a file extension, or `None` if it is not one.
+pub(crate) fn columnar_format(extension: &str) -> Option<&'static str> {
+    match extension {
+        "parquet" => Some("parquet"),
+        "feather" | "arrow" => Some("arrow"),
+        "avro" => Some("avro"),
+        _ => None,
+    }
+}
+
+/// Reads column names and types plus row and row-group counts from the footer or header
+/// of a Parquet, Arrow IPC (Feather v2) or Avro file, without decoding data pages.
+pub(crate) fn extract_columnar_metadata<R: Read + Seek>(reader: &mut R, format: &str) -> Result<ColumnarMetadata> {
+    match format {
+        "parquet" => parquet_metadata(reader),
+        "arrow" => arrow_metadata(reader),
+        "avro" => avro_metadata(reader),
+        _ => anyhow::bail!("Unsupported columnar format: {}", format),
+    }
+}
+
+fn new_metadata(format: &str, fields: Vec<(String, String)>) -> ColumnarMetadata {
+    let (columns, column_types): (Vec<String>, Vec<String>) = fields
+        .into_iter()
+        .map(|(name, data_type)| (redact_nhs_numbers(&name), data_type))
+        .unzip();
+
+    ColumnarMetadata {
+        format: format.to_string(),
+        canonical_columns: Vec::new(),
+        column_similarity_hash: calculate_column_similarity_hash(&columns),
+        columns,
+        column_types,
+        row_count: 0,
+        row_group_count: 0,
+        created_by: None,
+    }
+}
+
+/// Reads `len` bytes ending `from_end` bytes before the end of the file.
+fn read_from_end<R: Read + Seek>(reader: &mut R, from_end: u64, len: u64) -> Result<Vec<u8>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    if from_end.checked_add(len).is_none_or(|total| total > file_len) {
+        anyhow::bail!("Footer extends past the start of the file");
+    }
+    reader.seek(SeekFrom::Start(file_len - from_end - len))?;
+    read_declared(reader, len)
+}
+
+/// Reads a block whose length was read from the file. The buffer grows as bytes arrive,
+/// so a corrupt length fails at the end of the file instead of allocating up front.
+fn read_declared<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+    if len > MAX_FOOTER_SIZE {
+        anyhow::bail!("Declared length of {} bytes is too large", len);
+    }
+    let mut buffer = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut buffer)?;
+    if (buffer.len() as u64) < len {
+        anyhow::bail!("File ends inside a block of {} bytes", len);
+    }
+    Ok(buffer)
+}
+
+fn parquet_metadata<R: Read + Seek>(reader: &mut R) -> Result<ColumnarMetadata> {
+    let tail = read_from_end(reader, 0, PARQUET_TAIL_LEN)?;
+    if &tail[4..] != PARQUET_MAGIC {
+        anyhow::bail!("Not a Parquet file, or the footer is encrypted");
+    }
+    let footer_len = u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]) as u64;
+    let footer = read_from_end(reader, PARQUET_TAIL_LEN, footer_len)?;
+    let parquet_meta = ParquetMetaDataReader::decode_metadata(&footer)?;
+    let file_meta = parquet_meta.file_metadata();
+
+    // Leaf columns, with nested fields as dotted paths
+    let fields = file_meta
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|column| {
+            let data_type = match column.converted_type() {
+                ConvertedType::NONE => column.physical_type().to_string(),
+                converted => converted.to_string(),
+            };
+            (column.path().string(), data_type)
+        })
+        .collect();
+
+    let mut metadata = new_metadata("parquet", fields);
+    metadata.row_count = file_meta.num_rows().max(0) as u64;
+    metadata.row_group_count = parquet_meta.num_row_groups();
+    metadata.created_by = file_meta.created_by().map(|created_by| created_by.to_string());
+    Ok(metadata)
+}
+
+fn arrow_metadata<R: Read + Seek>(reader: &mut R) -> Result<ColumnarMetadata> {
+    let tail = read_from_end(reader, 0, ARROW_TAIL_LEN)?;
+    if &tail[4..] != ARROW_MAGIC {
+        anyhow::bail!("Not an Arrow IPC file (Feather v1 files are not supported)");
+    }
+    let footer_len = u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]) as u64;
+    let footer_bytes = read_from_end(reader, ARROW_TAIL_LEN, footer_len)?;
+    let footer = arrow_ipc::root_as_footer(&footer_bytes)
+        .map_err(|e| anyhow::anyhow!("Invalid Arrow footer: {}", e))?;
+
+    let fields = footer
+        .schema()
+        .map(|schema| {
+            arrow_ipc::convert::fb_to_schema(schema)
+                .fields()
+                .iter()
+                .map(|field| (field.name().to_string(), field.data_type().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut metadata = new_metadata("arrow", fields);
+
+    // Row counts live in each record batch's message header, which sits before its body
+    for block in footer.recordBatches().iter().flatten() {
+        reader.seek(SeekFrom::Start(block.offset().max(0) as u64))?;
+        let message = read_declared(reader, block.metaDataLength().max(0) as u64)?;
+
+        let prefix_len = if message.len() >= 8
+            && u32::from_le_bytes([message[0], message[1], message[2], message[3]]) == ARROW_CONTINUATION
+        {
+            8
+        } else {
+            4
+        };
+        let rows = message
+            .get(prefix_len..)
+            .and_then(|bytes| arrow_ipc::root_as_message(bytes).ok())
+            .and_then(|message| message.header_as_record_batch())
+            .map(|batch| batch.length().max(0) as u64)
+            .unwrap_or(0);
+
+        metadata.row_count = metadata.row_count.saturating_add(rows);
+        metadata.row_group_count += 1;
+    }
+
+    Ok(metadata)
+}
+
+fn avro_metadata<R: Read + Seek>(reader: &mut R) -> Result<ColumnarMetadata> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != AVRO_MAGIC {
+        anyhow::bail!("Not an Avro object container file");
+    }
+
+    // File metadata is an Avro map of string keys to bytes values
+    let mut schema = None;
+    loop {
+        let mut count = read_avro_long(reader)?;
+        if count == 0 {
+            break;
+        }
+        if count < 0 {
+            // Negative counts are followed by the block size in bytes
+            count = count.checked_neg().ok_or_else(|| anyhow::anyhow!("Corrupt Avro metadata count"))?;
+            read_avro_long(reader)?;
+        }
+        for _ in 0..count {
+            let key = read_avro_bytes(reader)?;
+            let value = read_avro_bytes(reader)?;
+            if key == b"avro.schema" {
+                schema = Some(value);
+            }
+        }
+    }
+    reader.seek(SeekFrom::Current(AVRO_SYNC_LEN))?;
+
+    let fields = schema
+        .and_then(|schema| serde_json::from_slice::<Value>(&schema).ok())
+        .and_then(|schema| schema.get("fields").and_then(|fields| fields.as_array()).cloned())
+        .map(|fields| {
+            fields
+                .iter()
+                .map(|field| {
+                    let name = field.get("name").and_then(|name| name.as_str()).unwrap_or("");
+                    let data_type = field.get("type").map(avro_type_name).unwrap_or_default();
+                    (name.to_string(), data_type)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut metadata = new_metadata("avro", fields);
+
+    // Each data block starts with its object count and byte size, so blocks are
+    // skipped without decoding their records
+    loop {
+        let objects = match read_avro_long(reader) {
+            Ok(objects) => objects,
+            Err(_) => break,
+        };
+        let size = read_avro_long(reader)?;
+        if objects < 0 || size < 0 {
+            anyhow::bail!("Corrupt Avro block header");
+        }
+        let skip = size.checked_add(AVRO_SYNC_LEN).ok_or_else(|| anyhow::anyhow!("Corrupt Avro block header"))?;
+        reader.seek(SeekFrom::Current(skip))?;
+        metadata.row_count = metadata.row_count.saturating_add(objects as u64);
+        metadata.row_group_count += 1;
+    }
+
+    Ok(metadata)
+}
+
+/// Renders an Avro schema type as a short name: primitives by name, unions joined with `|`
+/// and complex types by their logical type or kind.
+fn avro_type_name(value: &Value) -> String {
+    match value {
+        Value::String(name) => name.clone(),
+        Value::Array(members) => members.iter().map(avro_type_name).collect::<Vec<_>>().join("|"),
+        Value::Object(object) => object
+            .get("logicalType")
+            .or_else(|| object.get("type"))
+            .map(avro_type_name)
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Reads a zig-zag encoded variable-length Avro long.
+fn read_avro_long<R: Read>(reader: &mut R) -> Result<i64> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7F) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 63 {
+            anyhow::bail!("Avro long is too long");
+        }
+    }
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+fn read_avro_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = read_avro_long(reader)?;
+    if len < 0 {
+        anyhow::bail!("Corrupt Avro metadata length");
+    }
+    read_declared(reader, len as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::column::writer::ColumnWriter;
+    use parquet::data_type::ByteArray;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn extract(bytes: Vec<u8>, format: &str) -> Result<ColumnarMetadata> {
+        extract_columnar_metadata(&mut Cursor::new(bytes), format)
+    }
+
+    /// Zig-zag encodes an Avro long.
+    fn avro_long(value: i64) -> Vec<u8> {
+        let mut value = ((value << 1) ^ (value >> 63)) as u64;
+        let mut bytes = Vec::new();
+        while value >= 0x80 {
+            bytes.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+    fn avro_bytes(bytes: &[u8]) -> Vec<u8> {
+        let mut encoded = avro_long(bytes.len() as i64);
+        encoded.extend(bytes);
+        encoded
+    }
+
+    fn avro_header(entries: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut avro = AVRO_MAGIC.to_vec();
+        if !entries.is_empty() {
+            avro.extend(avro_long(entries.len() as i64));
+        }
+        for (key, value) in entries {
+            avro.extend(avro_bytes(key));
+            avro.extend(avro_bytes(value));
+        }
+        avro.extend(avro_long(0));
+        avro.extend([0u8; AVRO_SYNC_LEN as usize]);
+        avro
+    }
+
+    #[test]
+    fn avro_header_and_blocks() {
+        let schema = br#"{"type":"record","name":"r","fields":[{"name":"id","type":"long"},{"name":"dob","type":["null","string"]}]}"#;
+        let mut avro = avro_header(&[(b"avro.schema", schema)]);
+        for objects in [2, 3] {
+            avro.extend(avro_long(objects));
+            avro.extend(avro_bytes(&[0, 0, 0]));
+            avro.extend([0u8; AVRO_SYNC_LEN as usize]);
+        }
+
+        let metadata = extract(avro, "avro").unwrap();
+        assert_eq!(metadata.columns, ["id", "dob"]);
+        assert_eq!(metadata.column_types, ["long", "null|string"]);
+        assert_eq!(metadata.row_count, 5);
+        assert_eq!(metadata.row_group_count, 2);
+    }
+
+    #[test]
+    fn parquet_footer() {
+        let schema = "message export { REQUIRED INT64 id; OPTIONAL BYTE_ARRAY name (UTF8); \
+                      OPTIONAL group address { OPTIONAL BYTE_ARRAY postcode (UTF8); } }";
+        let schema = Arc::new(parse_message_type(schema).unwrap());
+        let mut parquet = Vec::new();
+        let mut writer =
+            SerializedFileWriter::new(&mut parquet, schema, Arc::new(WriterProperties::builder().build())).unwrap();
+        for rows in [3i64, 2] {
+            let mut row_group = writer.next_row_group().unwrap();
+            while let Some(mut column) = row_group.next_column().unwrap() {
+                match column.untyped() {
+                    ColumnWriter::Int64ColumnWriter(ids) => {
+                        ids.write_batch(&(0..rows).collect::<Vec<_>>(), None, None).unwrap();
+                    }
+                    ColumnWriter::ByteArrayColumnWriter(text) => {
+                        let values: Vec<ByteArray> = (0..rows).map(|_| ByteArray::from("x")).collect();
+                        let max_level = text.get_descriptor().max_def_level();
+                        text.write_batch(&values, Some(&vec![max_level; rows as usize]), None).unwrap();
+                    }
+                    _ => unreachable!(),
+                }
+                column.close().unwrap();
+            }
+            row_group.close().unwrap();
+        }
+        writer.close().unwrap();
+
+        let metadata = extract(parquet, "parquet").unwrap();
+        assert_eq!(metadata.columns, ["id", "name", "address.postcode"]);
+        assert_eq!(metadata.column_types, ["INT64", "UTF8", "UTF8"]);
+        assert_eq!(metadata.row_count, 5);
+        assert_eq!(metadata.row_group_count, 2);
+        assert!(metadata.created_by.is_some_and(|created_by| created_by.starts_with("parquet-rs")));
+    }
+
+    #[test]
+    fn avro_lengths_that_overflow_are_errors() {
+        let mut avro = AVRO_MAGIC.to_vec();
+        avro.extend(avro_long(i64::MIN));
+        assert!(extract(avro, "avro").is_err());
+
+        let mut avro = avro_header(&[]);
+        avro.extend(avro_long(1));
+        avro.extend(avro_long(i64::MAX));
+        assert!(extract(avro, "avro").is_err());
+
+        // A metadata value claiming far more bytes than the file holds
+        let mut avro = AVRO_MAGIC.to_vec();
+        avro.extend(avro_long(1));
+        avro.extend(avro_bytes(b"key"));
+        avro.extend(avro_long(MAX_FOOTER_SIZE as i64 - 1));
+        assert!(extract(avro, "avro").is_err());
+    }
+}
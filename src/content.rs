@@ -0,0 +1,481 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BinaryHeap, HashSet};
+use std::hash::BuildHasher;
+use std::sync::OnceLock;
+
+use crate::minhash;
+use crate::synonyms::ColumnSynonyms;
+use crate::{normalise_column_name, ColumnFingerprint, RowFingerprint};
+
+// Rows sampled from the start of each dataset
+const SAMPLE_ROWS: usize = 10_000;
+// Slots per column fingerprint; estimates are within about ±0.06 of the true Jaccard similarity
+const FINGERPRINT_LEN: usize = 64;
+// Columns with fewer distinct values (flags, sexes, small codes) overlap in unrelated datasets
+const MIN_DISTINCT_VALUES: usize = 10;
+// Estimated Jaccard similarity of sampled values at which two columns correspond
+const COLUMN_MATCH_THRESHOLD: f64 = 0.5;
+// A single matching column (often a row number) is not enough to link two datasets
+const MIN_MATCHED_COLUMNS: usize = 2;
+// Smallest row hashes kept per dataset; datasets with fewer distinct rows are compared exactly
+const ROW_SKETCH_LEN: usize = 256;
+
+/// Which fingerprints to take while reading a dataset's rows.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Fingerprints<'a> {
+    pub(crate) columns: bool,
+    pub(crate) rows: bool,
+    pub(crate) content_hash: bool,
+    // Row hashes pair values with canonical column names, so datasets grouped through the
+    // dictionary can share rows
+    pub(crate) synonyms: Option<&'a ColumnSynonyms>,
+}
+
+impl<'a> Fingerprints<'a> {
+    /// Returns a sampler if any fingerprint was asked for.
+    pub(crate) fn sampler(self) -> Option<ContentSampler<'a>> {
+        (self.columns || self.rows).then(|| ContentSampler {
+            fingerprints: self,
+            ..Default::default()
+        })
+    }
+
+    /// Returns a content hasher if the content hash was asked for.
+    pub(crate) fn hasher(self) -> Option<ContentHasher> {
+        self.content_hash.then(ContentHasher::default)
+    }
+}
+
+/// A dataset's columns that have enough distinct values to be matched on content, as
+/// (column name, signature).
+pub(crate) type ContentProfile = Vec<(String, Vec<u64>)>;
+
+/// Columns matched between two profiles, as (column in the first, column in the second,
+/// estimated similarity of their values).
+pub(crate) type ColumnMatches = Vec<(usize, usize, f64)>;
+
+/// Builds a MinHash fingerprint of each column's distinct values from the first
+/// `SAMPLE_ROWS` rows of a dataset, and a sketch of the hashes of all of its rows.
+#[derive(Default)]
+pub(crate) struct ContentSampler<'a> {
+    fingerprints: Fingerprints<'a>,
+    columns: Vec<(Vec<u64>, HashSet<u64>)>,
+    rows: usize,
+    // Hashed cells of the row being read, the hash of each column's matching name, and
+    // the smallest distinct row hashes so far, largest on top
+    row_cells: Vec<(usize, u64)>,
+    column_name_hashes: Vec<u64>,
+    row_hashes: BinaryHeap<u64>,
+    sketched_rows: HashSet<u64>,
+    sketch_truncated: bool,
+}
+
+impl ContentSampler<'_> {
+    /// True once no further rows are needed.
+    pub(crate) fn is_full(&self) -> bool {
+        !self.fingerprints.rows && self.rows >= SAMPLE_ROWS
+    }
+
+    pub(crate) fn add_row<I: IntoIterator<Item = S>, S: AsRef<str>>(&mut self, values: I, columns: &[String]) {
+        if self.is_full() {
+            return;
+        }
+        for (column, value) in values.into_iter().enumerate() {
+            self.add_value(column, value.as_ref());
+        }
+        self.end_row(columns);
+    }
+
+    /// Adds one cell of the current row; columns are numbered from 0 in any order.
+    pub(crate) fn add_value(&mut self, column: usize, value: &str) {
+        let Some(value) = normalise_value(value) else {
+            return;
+        };
+
+        // Salted so that a fingerprint cannot be checked against guessed values, such as
+        // every possible NHS number; fingerprints are only comparable within one scan
+        let hash = minhash::token_hash(&value) ^ salt();
+
+        if self.fingerprints.columns && self.rows < SAMPLE_ROWS {
+            if column >= self.columns.len() {
+                self.columns
+                    .resize_with(column + 1, || (vec![u64::MAX; FINGERPRINT_LEN], HashSet::new()));
+            }
+            let (signature, distinct) = &mut self.columns[column];
+            if distinct.insert(hash) {
+                minhash::add_token_hash(signature, hash);
+            }
+        }
+        if self.fingerprints.rows {
+            self.row_cells.push((column, hash));
+        }
+    }
+
+    /// Finishes the current row. Its hash pairs each value with its normalised column
+    /// name, or canonical name when a synonym dictionary is used, and ignores column
+    /// order, so the same rows match whichever order the columns were saved in; rows with
+    /// no values are skipped.
+    pub(crate) fn end_row(&mut self, columns: &[String]) {
+        self.rows += 1;
+        if self.row_cells.is_empty() {
+            return;
+        }
+
+        for column in &columns[self.column_name_hashes.len().min(columns.len())..] {
+            let name = match self.fingerprints.synonyms {
+                Some(synonyms) => synonyms.canonical_name(column),
+                None => normalise_column_name(column),
+            };
+            self.column_name_hashes.push(minhash::token_hash(&name));
+        }
+        let row_hash = self
+            .row_cells
+            .drain(..)
+            .filter_map(|(column, hash)| self.column_name_hashes.get(column).map(|&name| minhash::mix(name, hash)))
+            .fold(0u64, u64::wrapping_add);
+        self.add_row_hash(row_hash);
+    }
+
+    /// Keeps the `ROW_SKETCH_LEN` smallest distinct row hashes, so memory does not grow
+    /// with the number of rows.
+    fn add_row_hash(&mut self, hash: u64) {
+        if self.row_hashes.len() == ROW_SKETCH_LEN {
+            // Most rows of a long dataset hash above every kept hash
+            if self.row_hashes.peek().is_some_and(|&largest| hash >= largest) {
+                return;
+            }
+            if self.sketched_rows.contains(&hash) {
+                return;
+            }
+            if let Some(largest) = self.row_hashes.pop() {
+                self.sketched_rows.remove(&largest);
+            }
+            self.sketch_truncated = true;
+        } else if self.sketched_rows.contains(&hash) {
+            return;
+        }
+        self.sketched_rows.insert(hash);
+        self.row_hashes.push(hash);
+    }
+
+    /// Returns fingerprints for the named columns that held at least one value, and the
+    /// row fingerprint if rows were fingerprinted and any held values.
+    pub(crate) fn finish(self, columns: &[String]) -> (Vec<ColumnFingerprint>, Option<RowFingerprint>) {
+        let column_fingerprints = self
+            .columns
+            .into_iter()
+            .zip(columns)
+            .filter(|((_, distinct), _)| !distinct.is_empty())
+            .map(|((signature, distinct), column)| ColumnFingerprint {
+                column: column.clone(),
+                distinct_values: distinct.len(),
+                minhash: signature.iter().map(|&slot| format!("{:08x}", slot as u32)).collect(),
+            })
+            .collect();
+
+        let row_fingerprint = (!self.row_hashes.is_empty()).then(|| {
+            let hashes = self.row_hashes.into_sorted_vec();
+            let distinct_rows = if self.sketch_truncated {
+                estimate_distinct_rows(&hashes)
+            } else {
+                hashes.len()
+            };
+            RowFingerprint {
+                distinct_rows,
+                sketch: hashes.iter().map(|hash| format!("{:016x}", hash)).collect(),
+            }
+        });
+
+        (column_fingerprints, row_fingerprint)
+    }
+}
+
+/// Hashes a dataset's header and rows in order after normalising each cell, so a CSV file
+/// and an Excel sheet holding the same data hash the same whatever their quoting,
+/// whitespace or number formatting, or where on the sheet the data starts. Unlike
+/// fingerprints the hash is not salted, so it can be compared across scans like a CRC32
+/// hash.
+#[derive(Default)]
+pub(crate) struct ContentHasher {
+    // Each row's leading empty cells and the hash of the rest; rows are combined once the
+    // number of empty columns on the left of every row is known
+    rows: Vec<(usize, u64)>,
+    cells: Vec<u64>,
+}
+
+impl ContentHasher {
+    /// Adds the next row. Empty rows, trailing empty cells and columns left of the data
+    /// are ignored, as spreadsheets and their CSV copies disagree on all three.
+    pub(crate) fn add_row<I: IntoIterator<Item = S>, S: AsRef<str>>(&mut self, values: I) {
+        self.cells.clear();
+        for value in values {
+            // An empty cell still holds its place in the row
+            let cell = normalise_value(value.as_ref()).map_or(0, |value| minhash::token_hash(&value));
+            self.cells.push(cell);
+        }
+        while self.cells.last() == Some(&0) {
+            self.cells.pop();
+        }
+        let Some(leading) = self.cells.iter().position(|&cell| cell != 0) else {
+            return;
+        };
+
+        let row_hash = self.cells[leading..].iter().fold(0, |hash, &cell| minhash::mix(hash, cell));
+        self.rows.push((leading, row_hash));
+    }
+
+    /// Returns the hash as hexadecimal, or `None` if every row was empty.
+    pub(crate) fn finish(self) -> Option<String> {
+        let offset = self.rows.iter().map(|&(leading, _)| leading).min()?;
+        let hash = self.rows.iter().fold(0, |hash, &(leading, row_hash)| {
+            minhash::mix(hash, minhash::mix(row_hash, (leading - offset) as u64))
+        });
+        Some(format!("{:016x}", hash))
+    }
+}
+
+/// Estimates the share of each dataset's distinct rows found in the other from their row
+/// fingerprints (bottom-k sketches). The estimate is exact when neither dataset has more
+/// than `ROW_SKETCH_LEN` distinct rows. Returns `None` for an unreadable sketch.
+pub(crate) fn estimate_row_containment(a: &RowFingerprint, b: &RowFingerprint) -> Option<(f64, f64)> {
+    let decode = |fingerprint: &RowFingerprint| -> Option<HashSet<u64>> {
+        let sketch = &fingerprint.sketch;
+        (0..sketch.len() / 16)
+            .map(|slot| u64::from_str_radix(sketch.get(slot * 16..slot * 16 + 16)?, 16).ok())
+            .collect()
+    };
+    let (sketch_a, sketch_b) = (decode(a)?, decode(b)?);
+
+    // Below the largest hash of a truncated sketch, the sketch holds every row hash of its
+    // dataset, so hashes below both bounds are a uniform sample of each dataset's rows
+    // that can be looked up exactly in the other
+    let bound = |fingerprint: &RowFingerprint, sketch: &HashSet<u64>| {
+        if is_complete(fingerprint) { u64::MAX } else { sketch.iter().copied().max().unwrap_or(0) }
+    };
+    let limit = bound(a, &sketch_a).min(bound(b, &sketch_b));
+    let containment = |sketch: &HashSet<u64>, other: &HashSet<u64>| -> Option<f64> {
+        let sampled: Vec<&u64> = sketch.iter().filter(|&&hash| hash <= limit).collect();
+        let found = sampled.iter().filter(|hash| other.contains(hash)).count();
+        (!sampled.is_empty()).then(|| found as f64 / sampled.len() as f64)
+    };
+    Some((containment(&sketch_a, &sketch_b)?, containment(&sketch_b, &sketch_a)?))
+}
+
+/// Estimates how many distinct rows a dataset has from its full sketch: with `k` hashes
+/// kept, the `k`th smallest of `n` uniform hashes sits near `k / n` of the hash range. The
+/// standard error is about 1/√(k - 2), roughly 6% for 256 hashes.
+fn estimate_distinct_rows(sketch: &[u64]) -> usize {
+    let Some(&largest) = sketch.last() else {
+        return 0;
+    };
+    let fraction = (largest as f64 + 1.0) / 2f64.powi(64);
+    // A truncated sketch has seen more rows than it holds
+    (((sketch.len() - 1) as f64 / fraction).round() as usize).max(sketch.len() + 1)
+}
+
+/// True when a row fingerprint holds every distinct row hash, so comparisons are exact.
+pub(crate) fn is_complete(fingerprint: &RowFingerprint) -> bool {
+    fingerprint.distinct_rows <= ROW_SKETCH_LEN
+}
+
+/// Decodes the fingerprints of the columns with enough distinct values to be matched.
+pub(crate) fn content_profile(fingerprints: &[ColumnFingerprint]) -> ContentProfile {
+    fingerprints
+        .iter()
+        .filter(|fingerprint| fingerprint.distinct_values >= MIN_DISTINCT_VALUES)
+        .filter_map(|fingerprint| {
+            let signature = (0..FINGERPRINT_LEN)
+                .map(|slot| {
+                    let hex = fingerprint.minhash.get(slot * 8..slot * 8 + 8)?;
+                    u32::from_str_radix(hex, 16).ok().map(u64::from)
+                })
+                .collect::<Option<Vec<u64>>>()?;
+            Some((fingerprint.column.clone(), signature))
+        })
+        .collect()
+}
+
+/// Returns the pairs of datasets (by index, lower first) with at least one pair of
+/// columns likely to share values, found with locality-sensitive hashing.
+pub(crate) fn candidate_pairs(profiles: &[ContentProfile]) -> Vec<(usize, usize)> {
+    let mut index = minhash::LshIndex::default();
+    let mut owners = Vec::new();
+    for (dataset, profile) in profiles.iter().enumerate() {
+        for (_, signature) in profile {
+            index.insert(signature);
+            owners.push(dataset);
+        }
+    }
+
+    let mut pairs = HashSet::new();
+    for (item, &dataset) in owners.iter().enumerate() {
+        for other in index.candidates(item) {
+            if owners[other] > dataset {
+                pairs.insert((dataset, owners[other]));
+            }
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Matches the columns of two datasets by content, best matches first and each column at
+/// most once. Returns the share of columns matched (matches over the union, as for column
+/// names) and the matched pairs, or `None` when too few columns match.
+pub(crate) fn compare(a: &ContentProfile, b: &ContentProfile) -> Option<(f64, ColumnMatches)> {
+    let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+    for (i, (_, signature_a)) in a.iter().enumerate() {
+        for (j, (_, signature_b)) in b.iter().enumerate() {
+            let similarity = minhash::similarity(signature_a, signature_b);
+            if similarity >= COLUMN_MATCH_THRESHOLD {
+                candidates.push((similarity, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|x, y| y.0.total_cmp(&x.0).then(x.1.cmp(&y.1)).then(x.2.cmp(&y.2)));
+
+    let (mut used_a, mut used_b) = (HashSet::new(), HashSet::new());
+    let mut matches = Vec::new();
+    for (similarity, i, j) in candidates {
+        if used_a.contains(&i) || used_b.contains(&j) {
+            continue;
+        }
+        used_a.insert(i);
+        used_b.insert(j);
+        matches.push((i, j, similarity));
+    }
+
+    if matches.len() < MIN_MATCHED_COLUMNS {
+        return None;
+    }
+    let union = a.len() + b.len() - matches.len();
+    Some((matches.len() as f64 / union as f64, matches))
+}
+
+/// Makes text and numbers compare the way a reader would: CSV text "1.50" and an Excel
+/// number 1.5 are the same value, and case and surrounding spaces are ignored. Numbers
+/// with a leading zero, such as phone numbers and codes, stay text.
+fn normalise_value(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    if let Some(number) = normalise_decimal(value) {
+        return Some(number);
+    }
+    // Exponent notation, as some tools write very large and small numbers
+    if let Some((mantissa, _)) = value.split_once(['e', 'E']) {
+        if normalise_decimal(mantissa).is_some() {
+            if let Ok(number) = value.parse::<f64>() {
+                if number.is_finite() {
+                    return Some(number.to_string());
+                }
+            }
+        }
+    }
+    Some(value.to_lowercase())
+}
+
+/// Writes a plain decimal number the way Rust formats an `f64`, without going through
+/// one, so long integers keep every digit: no plus sign, trailing fractional zeros or
+/// negative zero. Returns `None` for anything else, including numbers with a leading zero.
+fn normalise_decimal(value: &str) -> Option<String> {
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if whole.is_empty() || !digits(whole) || !digits(fraction) || (whole.len() > 1 && whole.starts_with('0')) {
+        return None;
+    }
+
+    let fraction = fraction.trim_end_matches('0');
+    let mut number = String::new();
+    if negative && (whole != "0" || !fraction.is_empty()) {
+        number.push('-');
+    }
+    number.push_str(whole);
+    if !fraction.is_empty() {
+        number.push('.');
+        number.push_str(fraction);
+    }
+    Some(number)
+}
+
+fn salt() -> u64 {
+    static SALT: OnceLock<u64> = OnceLock::new();
+    *SALT.get_or_init(|| RandomState::new().hash_one("content fingerprint salt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content_hash(rows: &[&[&str]]) -> Option<String> {
+        let mut hasher = ContentHasher::default();
+        for row in rows {
+            hasher.add_row(row.iter());
+        }
+        hasher.finish()
+    }
+
+    #[test]
+    fn numbers_normalise_without_losing_digits() {
+        for (value, expected) in [
+            ("1.50", "1.5"),
+            ("+1.0", "1"),
+            ("-0.0", "0"),
+            ("-2.250", "-2.25"),
+            ("1e3", "1000"),
+            ("12345678901234567891", "12345678901234567891"),
+            ("0.5", "0.5"),
+            ("00123", "00123"),
+            ("07700 900123", "07700 900123"),
+            (" Text ", "text"),
+        ] {
+            assert_eq!(normalise_value(value).as_deref(), Some(expected), "{}", value);
+        }
+        assert_ne!(normalise_value("12345678901234567891"), normalise_value("12345678901234567890"));
+        assert_eq!(normalise_value("  "), None);
+    }
+
+    #[test]
+    fn content_hash_ignores_where_the_data_starts() {
+        let data = content_hash(&[&["id", "name"], &["1", "a"], &["2", "b"]]);
+        assert!(data.is_some());
+        assert_eq!(content_hash(&[&["", "id", "name"], &["", "1", "a"], &["", "2", "b", ""]]), data);
+        assert_eq!(content_hash(&[&[], &["", ""], &["id", "name"], &["1", "a"], &["2", "b"]]), data);
+        // Cells left of the data in only some rows are part of it
+        assert_ne!(content_hash(&[&["id", "name"], &["", "1", "a"], &["2", "b"]]), data);
+        assert_ne!(content_hash(&[&["id", "name"], &["2", "b"], &["1", "a"]]), data);
+        assert_eq!(content_hash(&[&[""]]), None);
+    }
+
+    fn row_fingerprint(rows: impl IntoIterator<Item = usize>) -> RowFingerprint {
+        let columns = vec!["id".to_string(), "name".to_string()];
+        let mut sampler = Fingerprints { rows: true, ..Default::default() }.sampler().unwrap();
+        for row in rows {
+            sampler.add_row([row.to_string(), format!("name {}", row)], &columns);
+        }
+        sampler.finish(&columns).1.unwrap()
+    }
+
+    #[test]
+    fn distinct_rows_are_counted_up_to_the_sketch_and_estimated_beyond_it() {
+        let small = row_fingerprint((0..100).chain(0..100));
+        assert_eq!(small.distinct_rows, 100);
+        assert_eq!(small.sketch.len(), 100 * 16);
+        assert!(is_complete(&small));
+
+        let large = row_fingerprint((0..20_000).chain(0..20_000));
+        assert_eq!(large.sketch.len(), ROW_SKETCH_LEN * 16);
+        assert!(!is_complete(&large));
+        assert!((16_000..=24_000).contains(&large.distinct_rows), "{}", large.distinct_rows);
+        // The same rows in another order keep the same sketch
+        let reversed = row_fingerprint((0..20_000).rev());
+        assert_eq!((reversed.distinct_rows, reversed.sketch), (large.distinct_rows, large.sketch));
+    }
+}
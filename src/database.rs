@@ -0,0 +1,141 @@
+use anyhow::Result;
+use rusqlite::{Connection, OpenFlags};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::{calculate_column_similarity_hash, redact_nhs_numbers, DatabaseMetadata, DatabaseTableMetadata};
+
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+const JET_MAGIC: &[u8; 16] = b"Standard Jet DB\0";
+const ACE_MAGIC: &[u8; 16] = b"Standard ACE DB\0";
+
+// The SQLite header is 100 bytes; the Access signature follows a 4-byte page header
+// and its version byte sits at 0x14
+const HEADER_LEN: u64 = 100;
+const ACCESS_MAGIC_OFFSET: usize = 4;
+const SQLITE_VERSION_OFFSET: usize = 96;
+const ACCESS_VERSION_OFFSET: usize = 0x14;
+
+/// Returns the database format for a file extension, or `None` if it is not a database.
+pub(crate) fn database_format(extension: &str) -> Option<&'static str> {
+    match extension {
+        "sqlite" | "sqlite3" | "db" => Some("sqlite"),
+        "mdb" | "accdb" => Some("access"),
+        _ => None,
+    }
+}
+
+pub(crate) fn read_header(path: &Path) -> Result<Vec<u8>> {
+    let mut header = Vec::new();
+    File::open(path)?.take(HEADER_LEN).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Identifies SQLite and Access databases from their header, with the version that
+/// last wrote them. Tables are listed separately because they need the file on disk.
+pub(crate) fn detect_database(header: &[u8]) -> Option<DatabaseMetadata> {
+    let access_magic = header.get(ACCESS_MAGIC_OFFSET..ACCESS_MAGIC_OFFSET + 16);
+
+    let (format, version) = if header.get(..16)? == SQLITE_MAGIC {
+        // Stored as the SQLITE_VERSION_NUMBER of the library that last modified the file
+        let version = header
+            .get(SQLITE_VERSION_OFFSET..SQLITE_VERSION_OFFSET + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .filter(|&number| number > 0)
+            .map(|number| format!("{}.{}.{}", number / 1_000_000, number / 1_000 % 1_000, number % 1_000));
+        ("sqlite", version)
+    } else if access_magic == Some(JET_MAGIC) || access_magic == Some(ACE_MAGIC) {
+        let version = header.get(ACCESS_VERSION_OFFSET).map(|&version| {
+            match version {
+                0x00 => "Jet 3 (Access 97)",
+                0x01 => "Jet 4 (Access 2000-2003)",
+                0x02 => "ACE 12 (Access 2007)",
+                0x03 => "ACE 14 (Access 2010)",
+                0x04 => "ACE 15 (Access 2013)",
+                0x05 => "ACE 16 (Access 2016 or later)",
+                _ => "unknown",
+            }
+            .to_string()
+        });
+        ("access", version)
+    } else {
+        return None;
+    };
+
+    Some(DatabaseMetadata {
+        format: format.to_string(),
+        version,
+        tables: Vec::new(),
+    })
+}
+
+/// Lists user tables with their columns and row counts, opening the file read-only and
+/// immutable so that no journal, WAL or lock files are created next to it.
+pub(crate) fn extract_sqlite_tables(path: &Path, max_rows: usize) -> Result<Vec<DatabaseTableMetadata>> {
+    let uri = format!("file:{}?immutable=1", uri_path(path));
+    let connection = Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+
+    let mut statement = connection.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let table_names = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter_map(|name| name.ok())
+        .collect::<Vec<_>>();
+
+    let mut tables = Vec::new();
+    for table_name in table_names {
+        let columns: Vec<String> = connection
+            .prepare("SELECT name FROM pragma_table_info(?1)")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([&table_name], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .unwrap_or_default()
+            .iter()
+            .map(|column| redact_nhs_numbers(column))
+            .collect();
+
+        // Counting stops at --max-rows, as it does for CSV files
+        let row_count = connection
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM (SELECT 1 FROM \"{}\" LIMIT ?1)",
+                    table_name.replace('"', "\"\"")
+                ),
+                [max_rows as i64],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count.max(0) as usize)
+            .unwrap_or(0);
+
+        tables.push(DatabaseTableMetadata {
+            table_name: redact_nhs_numbers(&table_name),
+            canonical_columns: Vec::new(),
+            column_similarity_hash: calculate_column_similarity_hash(&columns),
+            columns,
+            row_count,
+            stopped_row_count_at: (row_count >= max_rows).then_some(row_count),
+        });
+    }
+
+    Ok(tables)
+}
+
+/// Builds the path part of an SQLite URI filename, percent-encoding the characters that
+/// would otherwise end it. Windows drive paths take the `///C:/` form SQLite expects.
+fn uri_path(path: &Path) -> String {
+    let mut path = path.to_string_lossy().replace('%', "%25").replace('?', "%3f").replace('#', "%23");
+    if cfg!(windows) {
+        path = path.replace('\\', "/");
+        if path.as_bytes().get(1) == Some(&b':') {
+            path.insert_str(0, "///");
+        }
+    }
+    path
+}
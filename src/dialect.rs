@@ -0,0 +1,402 @@
+use anyhow::Result;
+use csv::ReaderBuilder;
+use std::io::{BufRead, BufReader, Cursor, Read};
+
+use crate::content::Fingerprints;
+use crate::{calculate_column_similarity_hash, redact_nhs_numbers, CsvDialect, CsvMetadata};
+
+// Enough of the file to see the header and a few dozen records
+const SAMPLE_SIZE: u64 = 64 * 1024;
+const SAMPLE_LINES: usize = 50;
+
+// Delimiters tried when sniffing, in order of preference when scores tie
+const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+// Share of sampled lines that must have the header's field count for a delimiter to be accepted
+const MIN_CONSISTENCY: f64 = 0.9;
+
+/// The layout of a text extract, as detected from a sample of its lines.
+#[derive(Debug, PartialEq)]
+enum Dialect {
+    Delimited { delimiter: u8, quote: u8 },
+    FixedWidth { columns: Vec<(usize, usize)> },
+}
+
+/// Reads delimited or fixed-width text (`.tsv`, `.tab`, `.txt`, `.dat`) through the CSV path.
+/// Returns `Ok(None)` when the file does not look like tabular text, so that free-text
+/// `.txt` files are left out of the inventory.
+pub(crate) fn extract_text_metadata<R: Read>(
+    mut source: R,
+    extension: &str,
+    max_rows: usize,
+    fingerprints: Fingerprints,
+) -> Result<Option<CsvMetadata>> {
+    let mut sample = Vec::new();
+    (&mut source).take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+    let truncated = sample.len() as u64 == SAMPLE_SIZE;
+
+    // NUL bytes mean a binary file, which .dat files often are
+    if sample.contains(&0) {
+        return Ok(None);
+    }
+
+    let lines = sample_lines(&sample, truncated);
+
+    let dialect = match extension {
+        // The extension already says tab-separated, so fall back to tabs if sniffing is inconclusive
+        "tsv" | "tab" => Some(sniff_delimited(&lines, b'\t').unwrap_or(Dialect::Delimited {
+            delimiter: b'\t',
+            quote: b'"',
+        })),
+        "dat" => sniff_delimited(&lines, b',').or_else(|| sniff_fixed_width(&lines)),
+        _ => sniff_delimited(&lines, b','),
+    };
+
+    let Some(dialect) = dialect else {
+        return Ok(None);
+    };
+
+    let full = Cursor::new(sample).chain(source);
+    let metadata = match dialect {
+        Dialect::Delimited { delimiter, quote } => read_delimited(full, delimiter, quote, max_rows, fingerprints)?,
+        Dialect::FixedWidth { columns } => read_fixed_width(full, columns, max_rows, fingerprints)?,
+    };
+    Ok(Some(metadata))
+}
+
+/// Splits the sample into non-empty lines, dropping a final line cut off by the sample size.
+fn sample_lines(sample: &[u8], truncated: bool) -> Vec<String> {
+    let text = String::from_utf8_lossy(sample);
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    if truncated {
+        lines.pop();
+    }
+
+    lines
+        .into_iter()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .take(SAMPLE_LINES)
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Picks the delimiter whose field count is non-zero in the header and matches it on
+/// nearly every sampled line. `preferred` wins ties.
+fn sniff_delimited(lines: &[String], preferred: u8) -> Option<Dialect> {
+    if lines.len() < 2 {
+        return None;
+    }
+
+    let quote = detect_quote(lines);
+    let mut candidates = vec![preferred];
+    candidates.extend(CANDIDATE_DELIMITERS.iter().filter(|&&d| d != preferred));
+
+    let mut best: Option<(u8, f64, usize)> = None;
+    for delimiter in candidates {
+        let header_count = count_delimiters(&lines[0], delimiter, quote);
+        if header_count == 0 {
+            continue;
+        }
+
+        let matching = lines
+            .iter()
+            .filter(|line| count_delimiters(line, delimiter, quote) == header_count)
+            .count();
+        let consistency = matching as f64 / lines.len() as f64;
+        if consistency < MIN_CONSISTENCY {
+            continue;
+        }
+
+        let better = match best {
+            Some((_, best_consistency, best_count)) => {
+                consistency > best_consistency
+                    || (consistency == best_consistency && header_count > best_count)
+            }
+            None => true,
+        };
+        if better {
+            best = Some((delimiter, consistency, header_count));
+        }
+    }
+
+    best.map(|(delimiter, _, _)| Dialect::Delimited { delimiter, quote })
+}
+
+/// Uses single quotes only when they appear to wrap fields and double quotes never do.
+fn detect_quote(lines: &[String]) -> u8 {
+    let starts_with = |quote: char| {
+        lines.iter().any(|line| {
+            line.starts_with(quote)
+                || CANDIDATE_DELIMITERS
+                    .iter()
+                    .any(|&d| line.contains(&format!("{}{}", d as char, quote)))
+        })
+    };
+    if !starts_with('"') && starts_with('\'') {
+        b'\''
+    } else {
+        b'"'
+    }
+}
+
+fn count_delimiters(line: &str, delimiter: u8, quote: u8) -> usize {
+    let mut in_quotes = false;
+    let mut count = 0;
+    for &byte in line.as_bytes() {
+        if byte == quote {
+            in_quotes = !in_quotes;
+        } else if byte == delimiter && !in_quotes {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Finds fixed-width columns as runs of character positions that are not blank on
+/// every sampled line. At least two columns are needed to call a file fixed-width.
+fn sniff_fixed_width(lines: &[String]) -> Option<Dialect> {
+    if lines.len() < 2 || !lines.iter().all(|line| line.is_ascii()) {
+        return None;
+    }
+
+    let width = lines.iter().map(|line| line.len()).max()?;
+    let mut occupied = vec![false; width];
+    for line in lines {
+        for (position, byte) in line.bytes().enumerate() {
+            if !byte.is_ascii_whitespace() {
+                occupied[position] = true;
+            }
+        }
+    }
+
+    let mut columns = Vec::new();
+    let mut start = None;
+    for (position, &used) in occupied.iter().enumerate() {
+        match (used, start) {
+            (true, None) => start = Some(position),
+            (false, Some(column_start)) => {
+                columns.push((column_start, position));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(column_start) = start {
+        columns.push((column_start, width));
+    }
+
+    // Every column needs a header, otherwise the gaps are just ragged free text
+    let header = lines[0].as_bytes();
+    let named = columns.iter().all(|&(start, end)| {
+        header
+            .get(start..end.min(header.len()))
+            .map(|name| name.iter().any(|b| !b.is_ascii_whitespace()))
+            .unwrap_or(false)
+    });
+
+    if columns.len() >= 2 && named {
+        Some(Dialect::FixedWidth { columns })
+    } else {
+        None
+    }
+}
+
+fn read_delimited<R: Read>(
+    source: R,
+    delimiter: u8,
+    quote: u8,
+    max_rows: usize,
+    fingerprints: Fingerprints,
+) -> Result<CsvMetadata> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .delimiter(delimiter)
+        .quote(quote)
+        .flexible(true)
+        .from_reader(source);
+
+    let headers = reader.headers()?.clone();
+    let columns: Vec<String> = headers
+        .iter()
+        .map(|name| redact_nhs_numbers(name.trim_start_matches('\u{feff}').trim()))
+        .collect();
+
+    let mut row_count = 0;
+    let mut stopped_at = None;
+    let mut sampler = fingerprints.sampler();
+    let mut hasher = fingerprints.hasher();
+    if let Some(hasher) = &mut hasher {
+        hasher.add_row(headers.iter().map(|name| name.trim_start_matches('\u{feff}')));
+    }
+
+    for record in reader.records().flatten() {
+        if let Some(sampler) = &mut sampler {
+            sampler.add_row(record.iter(), &columns);
+        }
+        if let Some(hasher) = &mut hasher {
+            hasher.add_row(record.iter());
+        }
+        row_count += 1;
+        if row_count >= max_rows {
+            stopped_at = Some(row_count);
+            break;
+        }
+    }
+
+    let (content_fingerprints, row_fingerprint) = sampler.map(|sampler| sampler.finish(&columns)).unwrap_or_default();
+
+    Ok(CsvMetadata {
+        canonical_columns: Vec::new(),
+        column_similarity_hash: calculate_column_similarity_hash(&columns),
+        content_hash: hasher.filter(|_| stopped_at.is_none()).and_then(|hasher| hasher.finish()),
+        content_fingerprints,
+        row_fingerprint,
+        columns,
+        row_count,
+        stopped_row_count_at: stopped_at,
+        dialect: Some(CsvDialect {
+            format: "delimited".to_string(),
+            delimiter: Some((delimiter as char).to_string()),
+            quote: Some((quote as char).to_string()),
+            column_widths: Vec::new(),
+        }),
+    })
+}
+
+fn read_fixed_width<R: Read>(
+    source: R,
+    columns: Vec<(usize, usize)>,
+    max_rows: usize,
+    fingerprints: Fingerprints,
+) -> Result<CsvMetadata> {
+    let mut lines = BufReader::new(source)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter(|line| !line.trim().is_empty());
+
+    let header = lines.next().unwrap_or_default();
+    let header = header.trim_start_matches('\u{feff}');
+    let names: Vec<String> = columns
+        .iter()
+        .map(|&(start, end)| {
+            let name = header.get(start..end.min(header.len())).unwrap_or("");
+            redact_nhs_numbers(name.trim())
+        })
+        .collect();
+
+    let mut row_count = 0;
+    let mut stopped_at = None;
+    let mut sampler = fingerprints.sampler();
+    let mut hasher = fingerprints.hasher();
+    if let Some(hasher) = &mut hasher {
+        hasher.add_row(columns.iter().map(|&(start, end)| header.get(start..end.min(header.len())).unwrap_or("")));
+    }
+    for line in lines {
+        let values = columns.iter().map(|&(start, end)| line.get(start..end.min(line.len())).unwrap_or(""));
+        if let Some(sampler) = &mut sampler {
+            sampler.add_row(values.clone(), &names);
+        }
+        if let Some(hasher) = &mut hasher {
+            hasher.add_row(values);
+        }
+        row_count += 1;
+        if row_count >= max_rows {
+            stopped_at = Some(row_count);
+            break;
+        }
+    }
+
+    // Widths run from the start of one column to the start of the next
+    let column_widths = columns
+        .iter()
+        .enumerate()
+        .map(|(index, &(start, end))| {
+            columns
+                .get(index + 1)
+                .map(|&(next_start, _)| next_start - start)
+                .unwrap_or(end - start)
+        })
+        .collect();
+
+    let (content_fingerprints, row_fingerprint) = sampler.map(|sampler| sampler.finish(&names)).unwrap_or_default();
+
+    Ok(CsvMetadata {
+        canonical_columns: Vec::new(),
+        column_similarity_hash: calculate_column_similarity_hash(&names),
+        content_hash: hasher.filter(|_| stopped_at.is_none()).and_then(|hasher| hasher.finish()),
+        content_fingerprints,
+        row_fingerprint,
+        columns: names,
+        row_count,
+        stopped_row_count_at: stopped_at,
+        dialect: Some(CsvDialect {
+            format: "fixed_width".to_string(),
+            delimiter: None,
+            quote: None,
+            column_widths,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        sample_lines(text.as_bytes(), false)
+    }
+
+    #[test]
+    fn sniffs_each_delimiter() {
+        for delimiter in ['\t', ';', '|'] {
+            let text = ["id", "name", "postcode", "1", "Smith, J", "LS1 4AP", "2", "Jones", "M1 1AA"]
+                .chunks(3)
+                .map(|row| row.join(&delimiter.to_string()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            assert_eq!(
+                sniff_delimited(&lines(&text), b','),
+                Some(Dialect::Delimited { delimiter: delimiter as u8, quote: b'"' }),
+                "{:?}",
+                delimiter
+            );
+        }
+    }
+
+    #[test]
+    fn single_quotes_are_used_only_when_they_wrap_fields() {
+        let single = lines("'id';'name'\n'1';'O''Brien, A'\n'2';'Smith'");
+        assert_eq!(detect_quote(&single), b'\'');
+        assert_eq!(sniff_delimited(&single, b','), Some(Dialect::Delimited { delimiter: b';', quote: b'\'' }));
+
+        assert_eq!(detect_quote(&lines("id,name\n1,O'Brien\n2,\"Smith, J\"")), b'"');
+        assert_eq!(detect_quote(&lines("id,name\n1,O'Brien\n2,'Smith'\n3,\"Jones\"")), b'"');
+    }
+
+    #[test]
+    fn sniffs_fixed_width_columns() {
+        let extract = lines(
+            "ID   NAME      DOB\n\
+             1    SMITH     19800101\n\
+             22   JONES     19751231\n\
+             333  O BRIEN   20010704",
+        );
+        assert!(sniff_delimited(&extract, b',').is_none());
+        assert_eq!(sniff_fixed_width(&extract), Some(Dialect::FixedWidth { columns: vec![(0, 3), (5, 12), (15, 23)] }));
+
+        // Gaps that the header leaves unnamed are ragged text, not columns
+        assert_eq!(sniff_fixed_width(&lines("NOTES\nsee   below\nmore  text")), None);
+    }
+
+    #[test]
+    fn free_text_is_not_tabular() {
+        let text = "Minutes of the data governance meeting\n\n\
+                    Attendees: the information governance lead, two analysts and the caldicott guardian.\n\
+                    Actions were agreed; the extract is to be reviewed, then deleted.\n\
+                    Next meeting: 4 March\n";
+        let metadata = extract_text_metadata(text.as_bytes(), "txt", usize::MAX, Fingerprints::default()).unwrap();
+        assert!(metadata.is_none());
+    }
+}
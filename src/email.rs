@@ -0,0 +1,194 @@
+use chrono::DateTime;
+use mail_parser::{Address, Message, MessageParser, MessagePart, MimeHeaders, PartType};
+use std::collections::BTreeSet;
+
+use crate::archive::ArchiveBudget;
+use crate::{process_embedded_file, redact_nhs_numbers, EmailAttachment, EmlMetadata, MimePart, ScanOptions};
+
+// Attached emails can contain further emails; stop descending after this many levels
+pub(crate) const MAX_ATTACHMENT_DEPTH: usize = 3;
+
+/// Parses an RFC 5322 message, returning `None` when the bytes are not a parseable email.
+pub(crate) fn extract_eml_metadata(
+    bytes: &[u8],
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> Option<EmlMetadata> {
+    let message = MessageParser::default().parse(bytes)?;
+    Some(eml_metadata_from_message(&message, options, depth, budget))
+}
+
+fn eml_metadata_from_message(
+    message: &Message,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> EmlMetadata {
+    let date = message
+        .date()
+        .and_then(|date| DateTime::from_timestamp(date.to_timestamp(), 0))
+        .map(|date| date.format("%Y-%m-%dT%H:%M").to_string());
+
+    // Attachments have no filesystem time of their own, so they take the sent date
+    let attachment_created = date.clone().unwrap_or_default();
+
+    let attachments = message
+        .attachments()
+        .map(|part| attachment_metadata(part, &attachment_created, options, depth, budget))
+        .collect();
+
+    EmlMetadata {
+        date,
+        subject: message.subject().map(|subject| redact_nhs_numbers(subject.trim())),
+        from: header_domains(message.from()),
+        to: header_domains(message.to()),
+        cc: header_domains(message.cc()),
+        mime_structure: Some(mime_structure(message, 0)),
+        attachments,
+    }
+}
+
+fn attachment_metadata(
+    part: &MessagePart,
+    created: &str,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> EmailAttachment {
+    let is_message = matches!(part.body, PartType::Message(_));
+    let mut name = part
+        .attachment_name()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| "attachment".to_string());
+    // Forwarded messages are often named after their subject; give them an extension
+    // so they are dispatched to the EML extractor
+    if is_message && !name.to_lowercase().ends_with(".eml") {
+        name.push_str(".eml");
+    }
+
+    attachment_from_bytes(&name, content_type(part), part.contents(), created, options, depth, budget)
+}
+
+/// Describes an attachment and, while under the depth limit, runs it through the extractors.
+pub(crate) fn attachment_from_bytes(
+    name: &str,
+    content_type: String,
+    contents: &[u8],
+    created: &str,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> EmailAttachment {
+    let metadata = if depth < MAX_ATTACHMENT_DEPTH {
+        Some(process_embedded_file(name, contents, created, options, depth + 1, budget))
+            .filter(|details| details.file_type.is_some())
+    } else {
+        None
+    };
+
+    EmailAttachment {
+        name: redact_nhs_numbers(name),
+        size: contents.len(),
+        content_type,
+        metadata,
+    }
+}
+
+/// Lists the domains of a header's addresses; local parts and display names are never reported.
+fn header_domains(address: Option<&Address>) -> Vec<String> {
+    let Some(address) = address else {
+        return Vec::new();
+    };
+    address_domains(address.iter().filter_map(|addr| addr.address()))
+}
+
+/// Returns the lowercased domains of the addresses, sorted and without duplicates.
+/// Values without a domain (such as Exchange distinguished names or display names) are left out.
+pub(crate) fn address_domains<'a>(addresses: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let domains: BTreeSet<String> = addresses
+        .into_iter()
+        .filter_map(|address| address.trim().rsplit_once('@'))
+        .map(|(_, domain)| domain.trim().trim_end_matches('>').to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .map(|domain| redact_nhs_numbers(&domain))
+        .collect();
+    domains.into_iter().collect()
+}
+
+fn content_type(part: &MessagePart) -> String {
+    match part.content_type() {
+        Some(content_type) => match content_type.subtype() {
+            Some(subtype) => format!("{}/{}", content_type.ctype(), subtype).to_lowercase(),
+            None => content_type.ctype().to_lowercase(),
+        },
+        None if matches!(part.body, PartType::Message(_)) => "message/rfc822".to_string(),
+        None => "text/plain".to_string(),
+    }
+}
+
+fn mime_structure(message: &Message, part_id: usize) -> MimePart {
+    let Some(part) = message.parts.get(part_id) else {
+        return MimePart {
+            content_type: "unknown".to_string(),
+            parts: Vec::new(),
+        };
+    };
+
+    let parts = match &part.body {
+        PartType::Multipart(children) => children
+            .iter()
+            .map(|child| mime_structure(message, *child))
+            .collect(),
+        PartType::Message(nested) => vec![mime_structure(nested, 0)],
+        _ => Vec::new(),
+    };
+
+    MimePart {
+        content_type: content_type(part),
+        parts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{content, PersonNameMode};
+
+    fn options(person_names: PersonNameMode) -> ScanOptions<'static> {
+        ScanOptions {
+            enable_hash: false,
+            max_rows: usize::MAX,
+            fingerprints: content::Fingerprints::default(),
+            person_names,
+            scan_archives: false,
+            archive_max_depth: 3,
+            archive_max_total_size: 1 << 20,
+        }
+    }
+
+    const MESSAGE: &[u8] = b"From: Jane Smith <jane.smith@NHS.net>\r\n\
+To: a.patel@nhs.net, \"Ops\" <ops@Example.org>, bob@example.org\r\n\
+Cc: /O=TRUST/OU=EXCHANGE/CN=RECIPIENTS/CN=JSMITH\r\n\
+Subject: Monthly extract\r\n\
+Date: Fri, 1 Mar 2024 09:30:00 +0000\r\n\
+\r\n\
+See attached.\r\n";
+
+    #[test]
+    fn addresses_are_reported_as_domains_only() {
+        for mode in [PersonNameMode::Keep, PersonNameMode::Redact, PersonNameMode::Pseudonymise] {
+            let metadata =
+                extract_eml_metadata(MESSAGE, &options(mode), 0, &mut ArchiveBudget::new(1 << 20)).unwrap();
+            assert_eq!(metadata.from, ["nhs.net"]);
+            assert_eq!(metadata.to, ["example.org", "nhs.net"]);
+            assert!(metadata.cc.is_empty());
+            assert_eq!(metadata.subject.as_deref(), Some("Monthly extract"));
+
+            let json = serde_json::to_string(&metadata).unwrap();
+            for local_part in ["jane", "smith", "patel", "ops", "bob"] {
+                assert!(!json.to_lowercase().contains(local_part), "{local_part} in {json}");
+            }
+        }
+    }
+}
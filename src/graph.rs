@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use quick_xml::escape::escape;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::{collect_files, round_similarity, GraphFormat, ScanResult};
+
+#[derive(Serialize)]
+struct Graph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+#[derive(Clone, Serialize)]
+struct GraphNode {
+    id: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_type: Option<String>,
+    created: String,
+}
+
+#[derive(Serialize)]
+struct GraphEdge {
+    source: String,
+    target: String,
+    kind: String,
+    weight: f64,
+}
+
+/// Builds the graph and writes it in the chosen format.
+pub(crate) fn write_graph(scan: &ScanResult, format: GraphFormat, path: &Path) -> Result<()> {
+    let graph = build_graph(scan);
+    let text = match format {
+        GraphFormat::Json => serde_json::to_string_pretty(&graph)?,
+        GraphFormat::Graphml => to_graphml(&graph),
+        GraphFormat::Dot => to_dot(&graph),
+    };
+    let mut file = File::create(path).context(format!("Failed to create graph file: {:?}", path))?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+/// Turns the scan's tables into a graph of files, sheets and tables. Each edge points from
+/// the earlier created node to the later one. Members of an identical bytes, schema or
+/// content entry link from the earliest member, and each column set in a fuzzy group
+/// links to its best match among the pairs scored while grouping, so copies form a tree
+/// rather than a clique.
+fn build_graph(scan: &ScanResult) -> Graph {
+    // Sheets and tables are dated by the file holding them
+    let mut known: HashMap<String, GraphNode> = HashMap::new();
+    let mut add_node = |id: String, kind: &str, file_type: &Option<String>, created: &str| {
+        known.insert(
+            id.clone(),
+            GraphNode {
+                id,
+                kind: kind.to_string(),
+                file_type: file_type.clone(),
+                created: created.to_string(),
+            },
+        );
+    };
+    for (file_path, file_details) in collect_files(&scan.directories) {
+        let (file_type, created) = (&file_details.file_type, &file_details.created);
+        if let Some(excel_meta) = &file_details.excel_metadata {
+            for sheet in &excel_meta.sheets {
+                add_node(format!("{} ({})", file_path, sheet.sheet_name), "sheet", file_type, created);
+            }
+        }
+        if let Some(database_meta) = &file_details.database_metadata {
+            for table in &database_meta.tables {
+                add_node(format!("{} ({})", file_path, table.table_name), "table", file_type, created);
+            }
+        }
+        add_node(file_path, "file", file_type, created);
+    }
+    let created = |id: &str| known.get(id).map_or("", |node| node.created.as_str());
+
+    let mut edges: HashMap<(String, String, &str), f64> = HashMap::new();
+    let mut add_edge = |a: &str, b: &str, kind: &'static str, weight: f64| {
+        let (source, target) = if (created(a), a) <= (created(b), b) { (a, b) } else { (b, a) };
+        let best = edges.entry((source.to_string(), target.to_string(), kind)).or_insert(weight);
+        *best = best.max(weight);
+    };
+
+    // Members of a group with identical bytes, schema or content all link from the earliest
+    let identical_groups = scan.crc32_similarity_table.iter().map(|entry| (&entry.sources, "identical_bytes"))
+        .chain(scan.column_similarity_table.iter().map(|entry| (&entry.sources, "identical_schema")))
+        .chain(scan.cross_format_duplicates.iter().map(|entry| (&entry.sources, "identical_content")));
+    for (sources, kind) in identical_groups {
+        let Some(earliest) = sources.iter().min_by(|a, b| (created(a), a).cmp(&(created(b), b))) else {
+            continue;
+        };
+        for member in sources.iter().filter(|member| *member != earliest) {
+            add_edge(earliest, member, kind, 1.0);
+        }
+    }
+
+    // Each distinct column set in a fuzzy group links from its earliest source to the best
+    // scoring set it was compared with while grouping, preferring sets that appeared earlier.
+    // Sets differing only in spelling or order share a schema hash and an identical-schema edge
+    let schema_hashes: HashMap<&str, &str> = scan
+        .column_similarity_table
+        .iter()
+        .flat_map(|entry| entry.sources.iter().map(|source| (source.as_str(), entry.hash.as_str())))
+        .collect();
+    let same_schema = |a: &str, b: &str| schema_hashes.get(a).is_some_and(|hash| schema_hashes.get(b) == Some(hash));
+    for group in &scan.fuzzy_similarity_groups {
+        let mut earliest: HashMap<usize, &str> = HashMap::new();
+        for (source, &set) in group.sources.iter().zip(&group.member_sets) {
+            let first = earliest.entry(set).or_insert(source);
+            if (created(source), source.as_str()) < (created(first), *first) {
+                *first = source;
+            }
+        }
+
+        let mut best: HashMap<usize, (bool, f64, &str)> = HashMap::new();
+        for &(a, b, score) in &group.scored_pairs {
+            let (Some(&first_a), Some(&first_b)) = (earliest.get(&a), earliest.get(&b)) else {
+                continue;
+            };
+            if same_schema(first_a, first_b) {
+                continue;
+            }
+            for (set, member, other) in [(a, first_a, first_b), (b, first_b, first_a)] {
+                let earlier = (created(other), other) < (created(member), member);
+                let better = best.get(&set).is_none_or(|&(best_earlier, best_score, best_other)| {
+                    (earlier, score) > (best_earlier, best_score)
+                        || ((earlier, score) == (best_earlier, best_score)
+                            && (created(other), other) < (created(best_other), best_other))
+                });
+                if better {
+                    best.insert(set, (earlier, score, other));
+                }
+            }
+        }
+        for (set, (_, score, other)) in best {
+            add_edge(other, earliest[&set], "fuzzy_schema", round_similarity(score));
+        }
+    }
+    for entry in &scan.row_overlap_table {
+        // Weighted by the Jaccard similarity of the two datasets' distinct rows
+        let union = entry.source_rows + entry.other_rows - entry.shared_rows;
+        let weight = if union > 0 { entry.shared_rows as f64 / union as f64 } else { 0.0 };
+        add_edge(&entry.source, &entry.other_source, "overlapping_content", round_similarity(weight));
+    }
+
+    let mut edges: Vec<GraphEdge> = edges
+        .into_iter()
+        .map(|((source, target, kind), weight)| GraphEdge {
+            source,
+            target,
+            kind: kind.to_string(),
+            weight,
+        })
+        .collect();
+    edges.sort_by(|a, b| {
+        (created(&a.target), &a.target, &a.source, &a.kind).cmp(&(created(&b.target), &b.target, &b.source, &b.kind))
+    });
+
+    // Only nodes on an edge are listed, oldest first
+    let mut nodes: Vec<GraphNode> = Vec::new();
+    let mut seen: HashSet<&str> = HashSet::new();
+    for id in edges.iter().flat_map(|edge| [&edge.source, &edge.target]) {
+        if seen.insert(id) {
+            nodes.push(known.get(id).cloned().unwrap_or_else(|| GraphNode {
+                id: id.clone(),
+                kind: "file".to_string(),
+                file_type: None,
+                created: String::new(),
+            }));
+        }
+    }
+    nodes.sort_by(|a, b| (&a.created, &a.id).cmp(&(&b.created, &b.id)));
+
+    Graph { nodes, edges }
+}
+
+fn to_graphml(graph: &Graph) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (id, target, name, kind) in [
+        ("label", "node", "label", "string"),
+        ("node_kind", "node", "kind", "string"),
+        ("file_type", "node", "file_type", "string"),
+        ("created", "node", "created", "string"),
+        ("edge_kind", "edge", "kind", "string"),
+        ("weight", "edge", "weight", "double"),
+    ] {
+        xml.push_str(&format!(
+            "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>\n",
+            id, target, name, kind
+        ));
+    }
+    xml.push_str("  <graph id=\"datasets\" edgedefault=\"directed\">\n");
+
+    // Paths are kept in a label, as tools handle short node IDs better
+    let mut node_ids: HashMap<&str, String> = HashMap::new();
+    for (index, node) in graph.nodes.iter().enumerate() {
+        let node_id = format!("n{}", index);
+        xml.push_str(&format!("    <node id=\"{}\">\n", node_id));
+        xml.push_str(&format!("      <data key=\"label\">{}</data>\n", escape(node.id.as_str())));
+        xml.push_str(&format!("      <data key=\"node_kind\">{}</data>\n", node.kind));
+        if let Some(file_type) = &node.file_type {
+            xml.push_str(&format!("      <data key=\"file_type\">{}</data>\n", escape(file_type.as_str())));
+        }
+        xml.push_str(&format!("      <data key=\"created\">{}</data>\n", escape(node.created.as_str())));
+        xml.push_str("    </node>\n");
+        node_ids.insert(node.id.as_str(), node_id);
+    }
+    for edge in &graph.edges {
+        xml.push_str(&format!(
+            "    <edge source=\"{}\" target=\"{}\">\n",
+            node_ids[edge.source.as_str()],
+            node_ids[edge.target.as_str()]
+        ));
+        xml.push_str(&format!("      <data key=\"edge_kind\">{}</data>\n", edge.kind));
+        xml.push_str(&format!("      <data key=\"weight\">{}</data>\n", edge.weight));
+        xml.push_str("    </edge>\n");
+    }
+
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+fn to_dot(graph: &Graph) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut dot = String::from("digraph datasets {\n");
+    for node in &graph.nodes {
+        dot.push_str(&format!(
+            "  {} [kind={}, file_type={}, created={}];\n",
+            quote(&node.id),
+            quote(&node.kind),
+            quote(node.file_type.as_deref().unwrap_or("")),
+            quote(&node.created)
+        ));
+    }
+    for edge in &graph.edges {
+        dot.push_str(&format!(
+            "  {} -> {} [kind={}, weight={}];\n",
+            quote(&edge.source),
+            quote(&edge.target),
+            quote(&edge.kind),
+            edge.weight
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_run_from_the_earliest_member() {
+        let file = |name: &str, file_type: &str, created: &str| {
+            serde_json::json!({ "name": name, "created": created, "file_type": file_type, "embedded_files": [] })
+        };
+        let scan: ScanResult = serde_json::from_value(serde_json::json!({
+            "scan_directory": "data",
+            "column_hash_scheme": { "version": 2, "algorithm": "xxh64", "order_sensitive": false },
+            "directories": [{
+                "path": "data",
+                "files": [
+                    file("copy.csv", "csv", "2023-05-01T10:00"),
+                    file("original.csv", "csv", "2021-01-01T09:00"),
+                    file("extract.xlsx", "excel", "2022-03-01T12:00"),
+                    file("later.csv", "csv", "2024-01-01T08:00"),
+                ],
+            }],
+            "column_similarity_table": [{
+                "hash": "a", "example_columns": ["id"], "sources": ["data/later.csv", "data/original.csv"],
+            }],
+            "crc32_similarity_table": [{ "hash": "b", "sources": ["data/copy.csv", "data/original.csv"] }],
+            "cross_format_duplicates": [{
+                "content_hash": "c", "formats": ["csv", "excel"], "sources": ["data/copy.csv", "data/extract.xlsx"],
+            }],
+            "row_overlap_table": [{
+                "source": "data/later.csv", "other_source": "data/extract.xlsx", "relationship": "subset",
+                "shared_rows": 10, "source_rows": 10, "other_rows": 40,
+                "source_shared_percent": 100.0, "other_shared_percent": 25.0, "estimated": false,
+            }],
+            "fuzzy_similarity_groups": [],
+            "column_index": [],
+        }))
+        .unwrap();
+
+        let graph = build_graph(&scan);
+        let edges: Vec<(&str, &str, &str, f64)> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.source.as_str(), edge.target.as_str(), edge.kind.as_str(), edge.weight))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ("data/extract.xlsx", "data/copy.csv", "identical_content", 1.0),
+                ("data/original.csv", "data/copy.csv", "identical_bytes", 1.0),
+                ("data/extract.xlsx", "data/later.csv", "overlapping_content", 0.25),
+                ("data/original.csv", "data/later.csv", "identical_schema", 1.0),
+            ]
+        );
+        let nodes: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+        assert_eq!(nodes, ["data/original.csv", "data/extract.xlsx", "data/copy.csv", "data/later.csv"]);
+    }
+}
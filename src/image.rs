@@ -0,0 +1,201 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use exif::{Context, In, Tag, Value};
+use std::collections::HashSet;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use crate::ImageMetadata;
+
+// Classic TIFF uses 32-bit offsets and 12-byte IFD entries; BigTIFF 64-bit offsets and 20-byte entries
+const TIFF_CLASSIC: u16 = 42;
+const TIFF_BIG: u16 = 43;
+// A damaged IFD chain can loop or run on; no real document has this many pages
+const MAX_TIFF_PAGES: usize = 10_000;
+
+/// Returns the image format for a file extension, or `None` if it is not an image.
+pub(crate) fn image_format(extension: &str) -> Option<&'static str> {
+    match extension {
+        "jpg" | "jpeg" => Some("jpeg"),
+        "png" => Some("png"),
+        "tif" | "tiff" => Some("tiff"),
+        _ => None,
+    }
+}
+
+/// Reads dimensions, TIFF page count and EXIF capture details from an image header,
+/// without decoding pixel data.
+pub(crate) fn extract_image_metadata<R: BufRead + Seek>(reader: &mut R, format: &str) -> Result<ImageMetadata> {
+    reader.seek(SeekFrom::Start(0))?;
+    let size = imagesize::reader_size(&mut *reader).map_err(|e| anyhow::anyhow!("Unreadable image header: {}", e))?;
+
+    let mut metadata = ImageMetadata {
+        format: format.to_string(),
+        width: size.width,
+        height: size.height,
+        page_count: None,
+        captured: None,
+        camera_model: None,
+        has_gps: false,
+    };
+
+    if format == "tiff" {
+        reader.seek(SeekFrom::Start(0))?;
+        metadata.page_count = count_tiff_pages(reader).ok();
+    }
+
+    // Images without EXIF (most PNGs and many scans) just lack these fields
+    reader.seek(SeekFrom::Start(0))?;
+    if let Ok(exif) = exif::Reader::new().read_from_container(reader) {
+        let text = |tag: Tag| {
+            exif.get_field(tag, In::PRIMARY).and_then(|field| match &field.value {
+                Value::Ascii(values) => values
+                    .first()
+                    .map(|value| String::from_utf8_lossy(value).trim().to_string())
+                    .filter(|value| !value.is_empty()),
+                _ => None,
+            })
+        };
+
+        metadata.captured = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+            .into_iter()
+            .find_map(|tag| text(tag).and_then(|value| normalise_exif_date(&value)));
+
+        // Model usually repeats the make ("Canon EOS 5D"); scanners often do not ("fi-7160")
+        metadata.camera_model = match (text(Tag::Make), text(Tag::Model)) {
+            (Some(make), Some(model)) if !model.to_lowercase().starts_with(&make.to_lowercase()) => {
+                Some(format!("{} {}", make, model))
+            }
+            (make, model) => model.or(make),
+        };
+
+        metadata.has_gps = exif
+            .fields()
+            .any(|field| field.tag.context() == Context::Gps && field.tag != Tag::GPSVersionID);
+    }
+
+    Ok(metadata)
+}
+
+/// Converts an EXIF `YYYY:MM:DD HH:MM:SS` timestamp to `YYYY-MM-DDTHH:MM`.
+/// EXIF times carry no zone, so they are reported as recorded by the device.
+fn normalise_exif_date(value: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(value, "%Y:%m:%d %H:%M:%S")
+        .ok()
+        .map(|datetime| datetime.format("%Y-%m-%dT%H:%M").to_string())
+}
+
+/// Counts the pages of a TIFF file by following its chain of image file directories.
+fn count_tiff_pages<R: Read + Seek>(reader: &mut R) -> Result<usize> {
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header[..8])?;
+    let little_endian = match &header[..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => anyhow::bail!("Not a TIFF file"),
+    };
+
+    let u16_from = |bytes: &[u8]| {
+        let array = [bytes[0], bytes[1]];
+        if little_endian { u16::from_le_bytes(array) } else { u16::from_be_bytes(array) }
+    };
+    let u64_from = |bytes: &[u8]| {
+        let mut array = [0u8; 8];
+        array[..bytes.len()].copy_from_slice(bytes);
+        if little_endian {
+            u64::from_le_bytes(array)
+        } else {
+            u64::from_be_bytes(array) >> (8 * (8 - bytes.len()))
+        }
+    };
+
+    let big = match u16_from(&header[2..4]) {
+        TIFF_CLASSIC => false,
+        TIFF_BIG => true,
+        _ => anyhow::bail!("Unknown TIFF version"),
+    };
+    let (count_len, entry_len, offset_len) = if big { (8, 20, 8) } else { (2, 12, 4) };
+
+    let mut offset = if big {
+        reader.read_exact(&mut header[8..16])?;
+        u64_from(&header[8..16])
+    } else {
+        u64_from(&header[4..8])
+    };
+
+    let mut visited = HashSet::new();
+    let mut pages = 0;
+    while offset != 0 && pages < MAX_TIFF_PAGES && visited.insert(offset) {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut count = [0u8; 8];
+        reader.read_exact(&mut count[..count_len])?;
+        let entries = u64_from(&count[..count_len]);
+        if entries > u16::MAX as u64 {
+            anyhow::bail!("Corrupt TIFF directory");
+        }
+
+        reader.seek(SeekFrom::Current((entries * entry_len) as i64))?;
+        let mut next = [0u8; 8];
+        reader.read_exact(&mut next[..offset_len])?;
+        offset = u64_from(&next[..offset_len]);
+        pages += 1;
+    }
+
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A classic TIFF whose directories sit at the given offsets, each with one entry and
+    // pointing at the next offset listed
+    fn classic_tiff(big_endian: bool, directories: &[(u32, u32)]) -> Vec<u8> {
+        let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let mut bytes = if big_endian { b"MM".to_vec() } else { b"II".to_vec() };
+        bytes.extend(u16_bytes(TIFF_CLASSIC));
+        bytes.extend(u32_bytes(directories[0].0));
+        for &(at, next) in directories {
+            bytes.resize(at as usize, 0);
+            bytes.extend(u16_bytes(1));
+            bytes.extend([0u8; 12]);
+            bytes.extend(u32_bytes(next));
+        }
+        bytes
+    }
+
+    #[test]
+    fn counts_classic_tiff_pages() {
+        for big_endian in [false, true] {
+            let tiff = classic_tiff(big_endian, &[(8, 26), (26, 0)]);
+            assert_eq!(count_tiff_pages(&mut Cursor::new(tiff)).unwrap(), 2);
+        }
+        assert!(count_tiff_pages(&mut Cursor::new(b"PK\x03\x04\0\0\0\0".to_vec())).is_err());
+        // A directory pointing past the end of the file
+        assert!(count_tiff_pages(&mut Cursor::new(classic_tiff(false, &[(8, 400)]))).is_err());
+    }
+
+    #[test]
+    fn counts_bigtiff_pages() {
+        let mut tiff = b"II".to_vec();
+        tiff.extend(TIFF_BIG.to_le_bytes());
+        tiff.extend(8u16.to_le_bytes());
+        tiff.extend(0u16.to_le_bytes());
+        tiff.extend(16u64.to_le_bytes());
+        for next in [52u64, 88, 0] {
+            tiff.extend(1u64.to_le_bytes());
+            tiff.extend([0u8; 20]);
+            tiff.extend(next.to_le_bytes());
+        }
+        assert_eq!(count_tiff_pages(&mut Cursor::new(tiff)).unwrap(), 3);
+    }
+
+    #[test]
+    fn looping_directory_chains_stop() {
+        let tiff = classic_tiff(false, &[(8, 26), (26, 44), (44, 26)]);
+        assert_eq!(count_tiff_pages(&mut Cursor::new(tiff)).unwrap(), 3);
+        let tiff = classic_tiff(true, &[(8, 8)]);
+        assert_eq!(count_tiff_pages(&mut Cursor::new(tiff)).unwrap(), 1);
+    }
+}
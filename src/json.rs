@@ -0,0 +1,213 @@
+use anyhow::Result;
+use serde::de::{DeserializeSeed, Error as _, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::content::{ContentSampler, Fingerprints};
+use crate::{calculate_column_similarity_hash, redact_nhs_numbers, JsonMetadata};
+
+/// Reads a JSON array of records or newline-delimited JSON, inferring columns as the
+/// union of record keys in first-seen order, with nested objects flattened to `a.b.c`.
+pub(crate) fn extract_json_metadata<R: Read>(
+    source: R,
+    extension: &str,
+    max_rows: usize,
+    fingerprints: Fingerprints,
+) -> Result<JsonMetadata> {
+    let mut inference = SchemaInference {
+        sampler: fingerprints.sampler(),
+        ..Default::default()
+    };
+
+    let format = if extension == "json" {
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(source));
+        let seed = RecordsSeed {
+            inference: &mut inference,
+            max_rows,
+        };
+        // The visitor stops with an error once --max-rows is reached; a file that breaks
+        // off part way still reports the records read so far
+        if let Err(e) = seed.deserialize(&mut deserializer) {
+            if inference.row_count == 0 {
+                return Err(e.into());
+            }
+        }
+        "json_array"
+    } else {
+        for line in BufReader::new(source).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Malformed lines are skipped, as unreadable CSV records are
+            if let Ok(record) = serde_json::from_str::<Value>(&line) {
+                inference.add_record(&record);
+                if inference.row_count >= max_rows {
+                    inference.stopped_at = Some(inference.row_count);
+                    break;
+                }
+            }
+        }
+        if inference.row_count == 0 {
+            anyhow::bail!("No JSON records found");
+        }
+        "ndjson"
+    };
+
+    let (content_fingerprints, row_fingerprint) = inference
+        .sampler
+        .take()
+        .map(|sampler| sampler.finish(&inference.columns))
+        .unwrap_or_default();
+
+    Ok(JsonMetadata {
+        format: format.to_string(),
+        canonical_columns: Vec::new(),
+        column_similarity_hash: calculate_column_similarity_hash(&inference.columns),
+        content_fingerprints,
+        row_fingerprint,
+        columns: inference.columns,
+        row_count: inference.row_count,
+        stopped_row_count_at: inference.stopped_at,
+    })
+}
+
+#[derive(Default)]
+struct SchemaInference<'a> {
+    columns: Vec<String>,
+    // Column position of each key path
+    seen: HashMap<String, usize>,
+    row_count: usize,
+    stopped_at: Option<usize>,
+    sampler: Option<ContentSampler<'a>>,
+}
+
+impl SchemaInference<'_> {
+    fn add_record(&mut self, record: &Value) {
+        self.row_count += 1;
+        if let Value::Object(fields) = record {
+            self.add_fields(fields, "");
+        }
+        if let Some(sampler) = &mut self.sampler {
+            sampler.end_row(&self.columns);
+        }
+    }
+
+    /// Records each key, descending into nested objects. Arrays are treated as values.
+    fn add_fields(&mut self, fields: &Map<String, Value>, prefix: &str) {
+        for (key, value) in fields {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+
+            match value {
+                Value::Object(nested) if !nested.is_empty() => self.add_fields(nested, &path),
+                _ => {
+                    let column = match self.seen.get(&path) {
+                        Some(&column) => column,
+                        None => {
+                            self.columns.push(redact_nhs_numbers(&path));
+                            self.seen.insert(path, self.columns.len() - 1);
+                            self.columns.len() - 1
+                        }
+                    };
+                    if let Some(sampler) = self.sampler.as_mut().filter(|sampler| !sampler.is_full()) {
+                        match value {
+                            Value::Null => {}
+                            Value::String(text) => sampler.add_value(column, text),
+                            other => sampler.add_value(column, &other.to_string()),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Walks the top-level array one record at a time, so the whole file is never held in memory.
+struct RecordsSeed<'a, 'b> {
+    inference: &'a mut SchemaInference<'b>,
+    max_rows: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for RecordsSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RecordsSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of records")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(record) = seq.next_element::<Value>()? {
+            self.inference.add_record(&record);
+            if self.inference.row_count >= self.max_rows {
+                self.inference.stopped_at = Some(self.inference.row_count);
+                return Err(A::Error::custom("row limit reached"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_json(text: &str, extension: &str, max_rows: usize) -> Result<JsonMetadata> {
+        extract_json_metadata(text.as_bytes(), extension, max_rows, Fingerprints::default())
+    }
+
+    #[test]
+    fn columns_are_the_union_of_flattened_keys_in_first_seen_order() {
+        let json = r#"[
+            {"id": 1, "patient": {"name": {"family": "Smith"}, "dob": "1980-01-01"}},
+            {"visit": "2024-03-01", "id": 2, "patient": {"nhs": null}, "tags": ["a"], "notes": {}}
+        ]"#;
+        let metadata = read_json(json, "json", usize::MAX).unwrap();
+        assert_eq!(metadata.format, "json_array");
+        assert_eq!(
+            metadata.columns,
+            ["id", "patient.name.family", "patient.dob", "visit", "patient.nhs", "tags", "notes"]
+        );
+        assert_eq!(metadata.row_count, 2);
+        assert_eq!(metadata.stopped_row_count_at, None);
+    }
+
+    #[test]
+    fn arrays_stop_at_max_rows_and_keep_rows_read_before_a_break() {
+        let json = r#"[{"id": 1}, {"id": 2, "name": "a"}, {"id": 3, "late": true}]"#;
+        let stopped = read_json(json, "json", 2).unwrap();
+        assert_eq!((stopped.row_count, stopped.stopped_row_count_at), (2, Some(2)));
+        assert_eq!(stopped.columns, ["id", "name"]);
+
+        let truncated = read_json(r#"[{"id": 1}, {"id": 2, "name": "a"}, {"id": 3, "la"#, "json", usize::MAX).unwrap();
+        assert_eq!((truncated.row_count, truncated.stopped_row_count_at), (2, None));
+        assert_eq!(truncated.columns, ["id", "name"]);
+
+        assert!(read_json(r#"[{"id": 1"#, "json", usize::MAX).is_err());
+        assert!(read_json(r#"{"id": 1}"#, "json", usize::MAX).is_err());
+    }
+
+    #[test]
+    fn ndjson_skips_malformed_lines() {
+        let ndjson = "{\"id\": 1, \"name\": \"a\"}\n\n{\"id\": 2,\nnot json\n{\"id\": 3, \"dob\": \"1980-01-01\"}\n";
+        let metadata = read_json(ndjson, "ndjson", usize::MAX).unwrap();
+        assert_eq!(metadata.format, "ndjson");
+        assert_eq!(metadata.columns, ["id", "name", "dob"]);
+        assert_eq!(metadata.row_count, 2);
+
+        assert!(read_json("not json\n{\n", "jsonl", usize::MAX).is_err());
+    }
+}
@@ -1,47 +1,200 @@
 use anyhow::{Context, Result};
-use calamine::{open_workbook_auto, Data, Range, Reader};
+use calamine::{open_workbook_auto, open_workbook_auto_from_rs, Data, Range, Reader, Sheets};
 use chrono::{DateTime, Utc};
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use crc32fast::Hasher;
 use csv::ReaderBuilder;
+use hmac::{Hmac, Mac};
 use indicatif::{ProgressBar, ProgressStyle};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::Sha256;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::hash::{BuildHasher, RandomState};
+use std::io::{BufReader, Cursor, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::time::SystemTime;
 use strsim::jaro_winkler;
 use walkdir::WalkDir;
 
+mod archive;
+mod columnar;
+mod content;
+mod database;
+mod dialect;
+mod email;
+mod graph;
+mod image;
+mod json;
+mod minhash;
+mod msg;
+mod ole;
+mod ooxml;
+mod pdf;
+mod protection;
+mod query;
+mod schema_diff;
+mod statistical;
+mod synonyms;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Directory to scan
-    #[arg(short, long)]
-    directory: PathBuf,
+    #[arg(short, long, required = true)]
+    directory: Option<PathBuf>,
 
     /// Output JSON file path
     #[arg(short, long, default_value = "output.json")]
     output: PathBuf,
 
-    /// Disable CRC32 hash calculation for files <= 128KB (hash is enabled by default)
+    /// Disable CRC32 hash calculation for files <= 128KB and normalised content hashes of
+    /// CSV files and Excel sheets (hashes are enabled by default)
     #[arg(long, default_value_t = false)]
     disable_hash: bool,
 
-    /// Maximum number of rows to process for CSV and Excel files (default: 524288)
+    /// Maximum number of rows to process for CSV, Excel and JSON files (default: 524288)
     #[arg(long, default_value_t = 524288)]
     max_rows: usize,
 
     /// Fuzzy similarity threshold for column grouping (0.0-1.0, default: 0.8, 0 disables)
     #[arg(long, default_value_t = 0.8)]
     fuzzy_threshold: f64,
+
+    /// How fuzzy groups are formed (components, greedy)
+    #[arg(long, value_enum, default_value_t = FuzzyClustering::Components)]
+    fuzzy_clustering: FuzzyClustering,
+
+    /// Column synonym dictionary (one `alias -> canonical` per line) applied before hashing and fuzzy matching
+    #[arg(long)]
+    column_synonyms: Option<PathBuf>,
+
+    /// Hash column names in their order rather than sorted, so datasets holding the same
+    /// columns in a different order get different column similarity hashes
+    #[arg(long, default_value_t = false)]
+    ordered_column_hash: bool,
+
+    /// Fingerprint sampled cell values of CSV, Excel and JSON columns so that fuzzy grouping
+    /// also matches datasets whose values overlap (default: disabled)
+    #[arg(long, default_value_t = false)]
+    content_fingerprints: bool,
+
+    /// Fingerprint the rows of CSV, Excel and JSON datasets to estimate row overlap between
+    /// datasets with the same columns (default: disabled)
+    #[arg(long, default_value_t = false)]
+    row_fingerprints: bool,
+
+    /// Also write a graph of related files, sheets and tables to this path
+    #[arg(long)]
+    graph_output: Option<PathBuf>,
+
+    /// Format of the graph written with --graph-output (json, graphml, dot)
+    #[arg(long, value_enum, default_value_t = GraphFormat::Json)]
+    graph_format: GraphFormat,
+
+    /// How to report person names found in document properties (keep, redact, pseudonymise)
+    #[arg(long, value_enum, default_value_t = PersonNameMode::Keep)]
+    person_names: PersonNameMode,
+
+    /// File holding the secret key for pseudonyms, so they match across scans using the same key (default: a new key each run).
+    /// Only valid with --person-names pseudonymise
+    #[arg(long)]
+    pseudonym_key_file: Option<PathBuf>,
+
+    /// Scan inside zip, tar, tar.gz and 7z archives (read-only, in memory)
+    #[arg(long, default_value_t = false)]
+    scan_archives: bool,
+
+    /// Maximum nesting depth of archives within archives (default: 3)
+    #[arg(long, default_value_t = 3)]
+    archive_max_depth: usize,
+
+    /// Maximum total bytes decompressed per archive, including nested archives (default: 1GB)
+    #[arg(long, default_value_t = 1024 * 1024 * 1024)]
+    archive_max_total_size: u64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Look up where columns appear in a previously written scan
+    Query {
+        /// Column names to look up
+        #[arg(required = true)]
+        columns: Vec<String>,
+
+        /// Scan output JSON file to search
+        #[arg(short, long, default_value = "output.json")]
+        scan: PathBuf,
+
+        /// Minimum Jaro-Winkler similarity for fuzzy matches (0.0-1.0, default: 0.8, 1 for exact matches only)
+        #[arg(long, default_value_t = 0.8)]
+        threshold: f64,
+
+        /// Column synonym dictionary to apply to the query, normally the one the scan used
+        #[arg(long)]
+        column_synonyms: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum PersonNameMode {
+    /// Report names as found (NHS numbers are still redacted)
+    Keep,
+    /// Replace names with [REDACTED]
+    Redact,
+    /// Replace names with a pseudonym keyed by a secret, the same for the same name
+    Pseudonymise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum FuzzyClustering {
+    /// Connected components: datasets linked by any chain of similar pairs share a group,
+    /// whatever order they were found in
+    Components,
+    /// The original seed-based grouping: each ungrouped dataset collects the later
+    /// datasets similar to it
+    Greedy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum GraphFormat {
+    /// JSON with `nodes` and `edges` arrays
+    Json,
+    /// GraphML, for Gephi, yEd and Cytoscape
+    Graphml,
+    /// Graphviz DOT
+    Dot,
+}
+
+/// Settings shared by every extractor, whether the file is on disk or embedded in another file
+#[derive(Clone, Copy, Debug)]
+struct ScanOptions<'a> {
+    enable_hash: bool,
+    max_rows: usize,
+    fingerprints: content::Fingerprints<'a>,
+    person_names: PersonNameMode,
+    scan_archives: bool,
+    archive_max_depth: usize,
+    archive_max_total_size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnHashScheme {
+    version: u32,
+    algorithm: String,
+    order_sensitive: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SimilarityHashEntry {
-    hash: u32,
+    hash: String,
     example_columns: Vec<String>,
     sources: Vec<String>,
 }
@@ -52,21 +205,104 @@ struct Crc32HashEntry {
     sources: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct RowOverlapEntry {
+    source: String,
+    other_source: String,
+    relationship: String,
+    shared_rows: usize,
+    source_rows: usize,
+    other_rows: usize,
+    source_shared_percent: f64,
+    other_shared_percent: f64,
+    estimated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CrossFormatDuplicateEntry {
+    content_hash: String,
+    formats: Vec<String>,
+    sources: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct FuzzySimilarityGroup {
-    group_id: usize,
+    group_id: String,
     similarity_score: f64,
+    min_similarity: f64,
+    max_similarity: f64,
     representative_columns: Vec<String>,
+    centroid_columns: Vec<String>,
+    sources: Vec<String>,
+    centroid_similarities: Vec<f64>,
+    schema_diff: SchemaDiff,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    column_correspondences: Vec<ColumnCorrespondence>,
+    // The distinct column set of each source, and the scores of the set pairs compared
+    // while grouping, so the graph does not score them again
+    #[serde(skip)]
+    member_sets: Vec<usize>,
+    #[serde(skip)]
+    scored_pairs: Vec<(usize, usize, f64)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SchemaDiff {
+    common_columns: Vec<String>,
+    partial_columns: Vec<PartialColumn>,
+    probable_renames: Vec<ColumnRename>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    order_variants: Vec<ColumnOrderVariant>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PartialColumn {
+    column: String,
+    sources: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnRename {
+    from: String,
+    to: String,
+    similarity: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnCorrespondence {
+    source: String,
+    column: String,
+    other_source: String,
+    other_column: String,
+    similarity: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnOrderVariant {
+    order: Vec<String>,
     sources: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ScanResult {
     scan_directory: String,
+    column_hash_scheme: ColumnHashScheme,
     directories: Vec<DirectoryEntry>,
     column_similarity_table: Vec<SimilarityHashEntry>,
     crc32_similarity_table: Vec<Crc32HashEntry>,
+    cross_format_duplicates: Vec<CrossFormatDuplicateEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    row_overlap_table: Vec<RowOverlapEntry>,
     fuzzy_similarity_groups: Vec<FuzzySimilarityGroup>,
+    column_index: Vec<ColumnIndexEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnIndexEntry {
+    column: String,
+    count: usize,
+    spellings: Vec<String>,
+    sources: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -86,23 +322,141 @@ struct FileDetails {
     #[serde(skip_serializing_if = "Option::is_none")]
     crc32_hash: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    encrypted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protection_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     csv_metadata: Option<CsvMetadata>,
     #[serde(skip_serializing_if = "Option::is_none")]
     excel_metadata: Option<ExcelMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_metadata: Option<JsonMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    columnar_metadata: Option<ColumnarMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    statistical_metadata: Option<StatisticalMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database_metadata: Option<DatabaseMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pdf_metadata: Option<PdfMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_metadata: Option<ImageMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    docx_metadata: Option<DocxMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pptx_metadata: Option<PptxMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ole_metadata: Option<OleMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eml_metadata: Option<EmlMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_metadata: Option<EmlMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archive_metadata: Option<ArchiveMetadata>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    embedded_files: Vec<FileDetails>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CsvMetadata {
     columns: Vec<String>,
     row_count: usize,
-    column_similarity_hash: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    canonical_columns: Vec<String>,
+    column_similarity_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stopped_row_count_at: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dialect: Option<CsvDialect>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    content_fingerprints: Vec<ColumnFingerprint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row_fingerprint: Option<RowFingerprint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnFingerprint {
+    column: String,
+    distinct_values: usize,
+    minhash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RowFingerprint {
+    distinct_rows: usize,
+    sketch: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvDialect {
+    format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delimiter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quote: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    column_widths: Vec<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonMetadata {
+    format: String,
+    columns: Vec<String>,
+    row_count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    canonical_columns: Vec<String>,
+    column_similarity_hash: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     stopped_row_count_at: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    content_fingerprints: Vec<ColumnFingerprint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row_fingerprint: Option<RowFingerprint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ColumnarMetadata {
+    format: String,
+    columns: Vec<String>,
+    column_types: Vec<String>,
+    row_count: u64,
+    row_group_count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    canonical_columns: Vec<String>,
+    column_similarity_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_by: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ExcelMetadata {
     sheets: Vec<SheetMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workbook_protected: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    properties: Option<DocumentProperties>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DocumentProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    company: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    application: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_version: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -110,22 +464,250 @@ struct SheetMetadata {
     sheet_name: String,
     columns: Vec<String>,
     row_count: usize,
-    column_similarity_hash: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    canonical_columns: Vec<String>,
+    column_similarity_hash: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     stopped_row_count_at: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protected: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    content_fingerprints: Vec<ColumnFingerprint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row_fingerprint: Option<RowFingerprint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StatisticalMetadata {
+    format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    case_count: Option<u64>,
+    columns: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    canonical_columns: Vec<String>,
+    column_similarity_hash: String,
+    variable_labels: Vec<Option<String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    value_labelled_columns: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DatabaseMetadata {
+    format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tables: Vec<DatabaseTableMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DatabaseTableMetadata {
+    table_name: String,
+    columns: Vec<String>,
+    row_count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    canonical_columns: Vec<String>,
+    column_similarity_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stopped_row_count_at: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PdfMetadata {
+    page_count: usize,
+    pdf_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    producer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creation_date: Option<String>,
+    encrypted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encryption_filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_layer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages_with_text: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_only_pages: Option<usize>,
+    attachment_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImageMetadata {
+    format: String,
+    width: usize,
+    height: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    captured: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    camera_model: Option<String>,
+    has_gps: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DocxMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    properties: Option<DocumentProperties>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    word_count: Option<usize>,
+    paragraph_count: usize,
+    table_count: usize,
+    tables: Vec<DocxTableMetadata>,
+    tracked_change_count: usize,
+    comment_count: usize,
+    embedded_object_count: usize,
+    image_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DocxTableMetadata {
+    header_row: Vec<String>,
+    row_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PptxMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    properties: Option<DocumentProperties>,
+    slide_count: usize,
+    table_count: usize,
+    tables: Vec<PptxTableMetadata>,
+    embedded_workbooks: Vec<String>,
+    embedded_object_count: usize,
+    image_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PptxTableMetadata {
+    slide: usize,
+    header_row: Vec<String>,
+    row_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OleMetadata {
+    properties: DocumentProperties,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    word_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slide_count: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EmlMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    from: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    to: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cc: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_structure: Option<MimePart>,
+    attachments: Vec<EmailAttachment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MimePart {
+    content_type: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    parts: Vec<MimePart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailAttachment {
+    name: String,
+    size: usize,
+    content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<FileDetails>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveMetadata {
+    archive_format: String,
+    member_count: usize,
+    total_uncompressed_size: u64,
+    members: Vec<FileDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_reached: Option<String>,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    if !args.directory.exists() {
-        anyhow::bail!("Directory does not exist: {:?}", args.directory);
+    if let Some(Command::Query { columns, scan, threshold, column_synonyms }) = &args.command {
+        let synonyms = column_synonyms.as_deref().map(synonyms::ColumnSynonyms::load).transpose()?;
+        return query::run_query(scan, columns, *threshold, synonyms.as_ref());
+    }
+
+    // Required unless a subcommand is given
+    let directory = args.directory.clone().context("--directory is required")?;
+
+    if !directory.exists() {
+        anyhow::bail!("Directory does not exist: {:?}", directory);
     }
 
-    println!("Scanning directory: {:?}", args.directory);
+    println!("Scanning directory: {:?}", directory);
     println!("Output file: {:?}", args.output);
 
-    let entries = scan_directory(&args.directory, !args.disable_hash, args.max_rows)?;
+    if let Some(key_file) = &args.pseudonym_key_file {
+        if args.person_names != PersonNameMode::Pseudonymise {
+            anyhow::bail!("--pseudonym-key-file is only used with --person-names pseudonymise");
+        }
+        let key = fs::read(key_file).context(format!("Failed to read pseudonym key: {:?}", key_file))?;
+        set_pseudonym_key(&key)?;
+    }
+
+    // Loaded before scanning so a bad file fails fast and row hashes can use canonical names
+    let synonyms = args.column_synonyms.as_deref().map(synonyms::ColumnSynonyms::load).transpose()?;
+
+    let options = ScanOptions {
+        enable_hash: !args.disable_hash,
+        max_rows: args.max_rows,
+        fingerprints: content::Fingerprints {
+            columns: args.content_fingerprints,
+            rows: args.row_fingerprints,
+            content_hash: !args.disable_hash,
+            synonyms: synonyms.as_ref(),
+        },
+        person_names: args.person_names,
+        scan_archives: args.scan_archives,
+        archive_max_depth: args.archive_max_depth,
+        archive_max_total_size: args.archive_max_total_size,
+    };
+
+    let mut entries = scan_directory(&directory, &options)?;
+
+    // Extractors hash names sorted; a synonym dictionary or ordered hashing rehashes them
+    if synonyms.is_some() || args.ordered_column_hash {
+        rehash_column_sets(&mut entries, synonyms.as_ref(), args.ordered_column_hash);
+    }
 
     // Build column similarity table
     let similarity_table = build_similarity_table(&entries);
@@ -133,23 +715,40 @@ fn main() -> Result<()> {
     // Build CRC32 similarity table
     let crc32_table = build_crc32_table(&entries);
 
+    // Build cross-format duplicates table
+    let cross_format_duplicates = build_cross_format_duplicates(&entries);
+
+    // Build row overlap table
+    let row_overlap_table = build_row_overlap_table(&entries);
+
     // Build fuzzy similarity groups
     let fuzzy_groups = if args.fuzzy_threshold > 0.0 {
-        build_fuzzy_similarity_groups(&entries, args.fuzzy_threshold)
+        build_fuzzy_similarity_groups(&entries, args.fuzzy_threshold, args.fuzzy_clustering)
     } else {
         Vec::new()
     };
 
+    // Build column index
+    let column_index = build_column_index(&entries);
+
     // Create the top-level result with absolute path
     let scan_result = ScanResult {
-        scan_directory: args.directory.canonicalize()
-            .unwrap_or_else(|_| args.directory.clone())
+        scan_directory: directory.canonicalize()
+            .unwrap_or_else(|_| directory.clone())
             .display()
             .to_string(),
+        column_hash_scheme: ColumnHashScheme {
+            version: COLUMN_HASH_VERSION,
+            algorithm: "xxh3-64".to_string(),
+            order_sensitive: args.ordered_column_hash,
+        },
         directories: entries,
         column_similarity_table: similarity_table,
         crc32_similarity_table: crc32_table,
+        cross_format_duplicates,
+        row_overlap_table,
         fuzzy_similarity_groups: fuzzy_groups,
+        column_index,
     };
 
     // Write JSON output
@@ -164,10 +763,15 @@ fn main() -> Result<()> {
     );
     println!("Output written to: {:?}", args.output);
 
+    if let Some(graph_output) = &args.graph_output {
+        graph::write_graph(&scan_result, args.graph_format, graph_output)?;
+        println!("Graph written to: {:?}", graph_output);
+    }
+
     Ok(())
 }
 
-fn scan_directory(path: &Path, enable_hash: bool, max_rows: usize) -> Result<Vec<DirectoryEntry>> {
+fn scan_directory(path: &Path, options: &ScanOptions) -> Result<Vec<DirectoryEntry>> {
     let mut dir_map: HashMap<PathBuf, Vec<FileDetails>> = HashMap::new();
 
     // First pass: count files for progress bar
@@ -175,7 +779,7 @@ fn scan_directory(path: &Path, enable_hash: bool, max_rows: usize) -> Result<Vec
         .into_iter()
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().is_file())
-        .filter(|e| is_supported_file_type(e.path()))
+        .filter(|e| is_supported_file_type(e.path(), options))
         .count();
 
     println!("Found {} files to process", file_count);
@@ -193,7 +797,7 @@ fn scan_directory(path: &Path, enable_hash: bool, max_rows: usize) -> Result<Vec
         .into_iter()
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().is_file())
-        .filter(|e| is_supported_file_type(e.path()))
+        .filter(|e| is_supported_file_type(e.path(), options))
     {
         let file_path = entry.path();
         let parent_dir = file_path
@@ -203,7 +807,7 @@ fn scan_directory(path: &Path, enable_hash: bool, max_rows: usize) -> Result<Vec
 
         pb.set_message(format!("Processing: {}", file_path.display()));
 
-        if let Ok(file_details) = process_file(file_path, enable_hash, max_rows) {
+        if let Ok(file_details) = process_file(file_path, options) {
             dir_map.entry(parent_dir).or_default().push(file_details);
         }
 
@@ -228,32 +832,271 @@ fn scan_directory(path: &Path, enable_hash: bool, max_rows: usize) -> Result<Vec
     Ok(entries)
 }
 
-fn build_similarity_table(directories: &[DirectoryEntry]) -> Vec<SimilarityHashEntry> {
-    let mut hash_map: HashMap<u32, (Vec<String>, Vec<String>)> = HashMap::new(); // (sources, example_columns)
+/// Lists every file in the scan with its source path, including files nested inside
+/// other files (email attachments, archive members) using `outer!/inner` virtual paths.
+fn collect_files(directories: &[DirectoryEntry]) -> Vec<(String, &FileDetails)> {
+    fn visit<'a>(source: String, file_details: &'a FileDetails, files: &mut Vec<(String, &'a FileDetails)>) {
+        for email_meta in [&file_details.eml_metadata, &file_details.msg_metadata].into_iter().flatten() {
+            for attachment in &email_meta.attachments {
+                if let Some(nested) = &attachment.metadata {
+                    visit(format!("{}!/{}", source, nested.name), nested, files);
+                }
+            }
+        }
+        if let Some(archive_meta) = &file_details.archive_metadata {
+            for member in &archive_meta.members {
+                visit(format!("{}!/{}", source, member.name), member, files);
+            }
+        }
+        for embedded in &file_details.embedded_files {
+            visit(format!("{}!/{}", source, embedded.name), embedded, files);
+        }
+        files.push((source, file_details));
+    }
 
+    let mut files = Vec::new();
     for dir_entry in directories {
         for file_details in &dir_entry.files {
             let file_path = format!("{}/{}", dir_entry.path, file_details.name);
+            visit(file_path, file_details, &mut files);
+        }
+    }
+    files
+}
 
-            // Collect CSV similarity hashes
-            if let Some(csv_meta) = &file_details.csv_metadata {
-                let entry = hash_map
-                    .entry(csv_meta.column_similarity_hash)
-                    .or_insert_with(|| (Vec::new(), csv_meta.columns.clone()));
-                entry.0.push(file_path.clone());
-            }
-
-            // Collect Excel similarity hashes (per sheet)
-            if let Some(excel_meta) = &file_details.excel_metadata {
-                for sheet in &excel_meta.sheets {
-                    let sheet_source = format!("{} ({})", file_path, sheet.sheet_name);
-                    let entry = hash_map
-                        .entry(sheet.column_similarity_hash)
-                        .or_insert_with(|| (Vec::new(), sheet.columns.clone()));
-                    entry.0.push(sheet_source);
+/// Adds canonical column names to every tabular column set in the scan, including nested
+/// files, when a synonym dictionary is given, and rehashes each set from the names fuzzy
+/// matching compares, keeping their order when `ordered`.
+fn rehash_column_sets(
+    directories: &mut [DirectoryEntry],
+    synonyms: Option<&synonyms::ColumnSynonyms>,
+    ordered: bool,
+) {
+    fn canonicalise(
+        columns: &[String],
+        canonical_columns: &mut Vec<String>,
+        hash: &mut String,
+        synonyms: Option<&synonyms::ColumnSynonyms>,
+        ordered: bool,
+    ) {
+        if let Some(synonyms) = synonyms {
+            *canonical_columns = columns.iter().map(|column| synonyms.canonical_name(column)).collect();
+        }
+        *hash = column_similarity_hash(matching_columns(columns, canonical_columns), ordered);
+    }
+
+    fn visit(file_details: &mut FileDetails, synonyms: Option<&synonyms::ColumnSynonyms>, ordered: bool) {
+        if let Some(csv_meta) = &mut file_details.csv_metadata {
+            canonicalise(
+                &csv_meta.columns,
+                &mut csv_meta.canonical_columns,
+                &mut csv_meta.column_similarity_hash,
+                synonyms,
+                ordered,
+            );
+        }
+        if let Some(excel_meta) = &mut file_details.excel_metadata {
+            for sheet in &mut excel_meta.sheets {
+                canonicalise(
+                    &sheet.columns,
+                    &mut sheet.canonical_columns,
+                    &mut sheet.column_similarity_hash,
+                    synonyms,
+                    ordered,
+                );
+            }
+        }
+        if let Some(json_meta) = &mut file_details.json_metadata {
+            canonicalise(
+                &json_meta.columns,
+                &mut json_meta.canonical_columns,
+                &mut json_meta.column_similarity_hash,
+                synonyms,
+                ordered,
+            );
+        }
+        if let Some(columnar_meta) = &mut file_details.columnar_metadata {
+            canonicalise(
+                &columnar_meta.columns,
+                &mut columnar_meta.canonical_columns,
+                &mut columnar_meta.column_similarity_hash,
+                synonyms,
+                ordered,
+            );
+        }
+        if let Some(statistical_meta) = &mut file_details.statistical_metadata {
+            canonicalise(
+                &statistical_meta.columns,
+                &mut statistical_meta.canonical_columns,
+                &mut statistical_meta.column_similarity_hash,
+                synonyms,
+                ordered,
+            );
+        }
+        if let Some(database_meta) = &mut file_details.database_metadata {
+            for table in &mut database_meta.tables {
+                canonicalise(
+                    &table.columns,
+                    &mut table.canonical_columns,
+                    &mut table.column_similarity_hash,
+                    synonyms,
+                    ordered,
+                );
+            }
+        }
+
+        for email_meta in [&mut file_details.eml_metadata, &mut file_details.msg_metadata].into_iter().flatten() {
+            for attachment in &mut email_meta.attachments {
+                if let Some(nested) = &mut attachment.metadata {
+                    visit(nested, synonyms, ordered);
                 }
             }
         }
+        if let Some(archive_meta) = &mut file_details.archive_metadata {
+            for member in &mut archive_meta.members {
+                visit(member, synonyms, ordered);
+            }
+        }
+        for embedded in &mut file_details.embedded_files {
+            visit(embedded, synonyms, ordered);
+        }
+    }
+
+    for dir_entry in directories {
+        for file_details in &mut dir_entry.files {
+            visit(file_details, synonyms, ordered);
+        }
+    }
+}
+
+/// Picks the names fuzzy matching compares: canonical names when a synonym dictionary
+/// was applied, otherwise the originals.
+fn matching_columns<'a>(columns: &'a [String], canonical_columns: &'a [String]) -> &'a [String] {
+    if canonical_columns.is_empty() { columns } else { canonical_columns }
+}
+
+/// A tabular column set found in the scan: a CSV, JSON, columnar or statistical file,
+/// an Excel sheet or a database table.
+struct ColumnSet<'a> {
+    source: String,
+    columns: &'a [String],
+    // The names fuzzy grouping compares
+    matching_columns: &'a [String],
+    hash: &'a str,
+    content_fingerprints: &'a [ColumnFingerprint],
+    row_fingerprint: Option<&'a RowFingerprint>,
+}
+
+impl<'a> ColumnSet<'a> {
+    fn new(
+        source: String,
+        columns: &'a [String],
+        canonical_columns: &'a [String],
+        hash: &'a str,
+        content_fingerprints: &'a [ColumnFingerprint],
+        row_fingerprint: Option<&'a RowFingerprint>,
+    ) -> Self {
+        ColumnSet {
+            source,
+            columns,
+            matching_columns: matching_columns(columns, canonical_columns),
+            hash,
+            content_fingerprints,
+            row_fingerprint,
+        }
+    }
+}
+
+/// Lists every tabular column set in the scan. CSV files are reported by path and Excel
+/// sheets as `path (sheet)`.
+fn collect_column_sets(directories: &[DirectoryEntry]) -> Vec<ColumnSet<'_>> {
+    let mut column_sets = Vec::new();
+
+    for (file_path, file_details) in collect_files(directories) {
+        if let Some(csv_meta) = &file_details.csv_metadata {
+            column_sets.push(ColumnSet::new(
+                file_path.clone(),
+                &csv_meta.columns,
+                &csv_meta.canonical_columns,
+                &csv_meta.column_similarity_hash,
+                &csv_meta.content_fingerprints,
+                csv_meta.row_fingerprint.as_ref(),
+            ));
+        }
+
+        if let Some(excel_meta) = &file_details.excel_metadata {
+            for sheet in &excel_meta.sheets {
+                let sheet_source = format!("{} ({})", file_path, sheet.sheet_name);
+                column_sets.push(ColumnSet::new(
+                    sheet_source,
+                    &sheet.columns,
+                    &sheet.canonical_columns,
+                    &sheet.column_similarity_hash,
+                    &sheet.content_fingerprints,
+                    sheet.row_fingerprint.as_ref(),
+                ));
+            }
+        }
+
+        if let Some(json_meta) = &file_details.json_metadata {
+            column_sets.push(ColumnSet::new(
+                file_path.clone(),
+                &json_meta.columns,
+                &json_meta.canonical_columns,
+                &json_meta.column_similarity_hash,
+                &json_meta.content_fingerprints,
+                json_meta.row_fingerprint.as_ref(),
+            ));
+        }
+
+        if let Some(columnar_meta) = &file_details.columnar_metadata {
+            column_sets.push(ColumnSet::new(
+                file_path.clone(),
+                &columnar_meta.columns,
+                &columnar_meta.canonical_columns,
+                &columnar_meta.column_similarity_hash,
+                &[],
+                None,
+            ));
+        }
+
+        if let Some(statistical_meta) = &file_details.statistical_metadata {
+            column_sets.push(ColumnSet::new(
+                file_path.clone(),
+                &statistical_meta.columns,
+                &statistical_meta.canonical_columns,
+                &statistical_meta.column_similarity_hash,
+                &[],
+                None,
+            ));
+        }
+
+        if let Some(database_meta) = &file_details.database_metadata {
+            for table in &database_meta.tables {
+                let table_source = format!("{} ({})", file_path, table.table_name);
+                column_sets.push(ColumnSet::new(
+                    table_source,
+                    &table.columns,
+                    &table.canonical_columns,
+                    &table.column_similarity_hash,
+                    &[],
+                    None,
+                ));
+            }
+        }
+    }
+
+    column_sets
+}
+
+fn build_similarity_table(directories: &[DirectoryEntry]) -> Vec<SimilarityHashEntry> {
+    let mut hash_map: HashMap<&str, (Vec<String>, Vec<String>)> = HashMap::new(); // (sources, example_columns)
+
+    for column_set in collect_column_sets(directories) {
+        let entry = hash_map
+            .entry(column_set.hash)
+            .or_insert_with(|| (Vec::new(), column_set.columns.to_vec()));
+        entry.0.push(column_set.source);
     }
 
     // Convert to sorted vector, only including hashes with multiple sources
@@ -261,120 +1104,513 @@ fn build_similarity_table(directories: &[DirectoryEntry]) -> Vec<SimilarityHashE
         .into_iter()
         .filter(|(_, (sources, _))| sources.len() > 1)  // Only show hashes with multiple sources
         .map(|(hash, (sources, example_columns))| SimilarityHashEntry {
-            hash,
+            hash: hash.to_string(),
             example_columns,
             sources
         })
         .collect();
 
-    similarity_table.sort_by_key(|entry| entry.hash);
+    similarity_table.sort_by(|a, b| a.hash.cmp(&b.hash));
     similarity_table
 }
 
 fn build_crc32_table(directories: &[DirectoryEntry]) -> Vec<Crc32HashEntry> {
     let mut hash_map: HashMap<String, Vec<String>> = HashMap::new();
 
-    for dir_entry in directories {
-        for file_details in &dir_entry.files {
-            let file_path = format!("{}/{}", dir_entry.path, file_details.name);
-
-            // Collect CRC32 hashes (only for files that have them)
-            if let Some(crc32_hash) = &file_details.crc32_hash {
-                hash_map
-                    .entry(crc32_hash.clone())
-                    .or_default()
-                    .push(file_path);
-            }
+    for (file_path, file_details) in collect_files(directories) {
+        // Collect CRC32 hashes (only for files that have them)
+        if let Some(crc32_hash) = &file_details.crc32_hash {
+            hash_map
+                .entry(crc32_hash.clone())
+                .or_default()
+                .push(file_path);
         }
     }
 
     // Convert to sorted vector, only including hashes with multiple sources
     let mut crc32_table: Vec<Crc32HashEntry> = hash_map
         .into_iter()
-        .filter(|(_, sources)| sources.len() > 1)  // Only show hashes with multiple sources
-        .map(|(hash, sources)| Crc32HashEntry { hash, sources })
+        .filter(|(_, sources)| sources.len() > 1)  // Only show hashes with multiple sources
+        .map(|(hash, sources)| Crc32HashEntry { hash, sources })
+        .collect();
+
+    crc32_table.sort_by(|a, b| a.hash.cmp(&b.hash));
+    crc32_table
+}
+
+// Share of a dataset's distinct rows that must be found in another for it to count as
+// contained there when the overlap is estimated from sampled sketches
+const ROW_CONTAINED_SHARE: f64 = 0.98;
+// Share of each dataset's distinct rows the other must hold for a near duplicate
+const ROW_NEAR_DUPLICATE_SHARE: f64 = 0.9;
+
+/// Compares the row fingerprints of datasets with the same column similarity hash and
+/// reports pairs that are identical, where one holds the other's rows (`superset` and
+/// `subset` describe `source`), or that share most of their rows.
+fn build_row_overlap_table(directories: &[DirectoryEntry]) -> Vec<RowOverlapEntry> {
+    let mut hash_map: HashMap<&str, Vec<(String, &RowFingerprint)>> = HashMap::new();
+    for column_set in collect_column_sets(directories) {
+        if let Some(row_fingerprint) = column_set.row_fingerprint {
+            hash_map
+                .entry(column_set.hash)
+                .or_default()
+                .push((column_set.source, row_fingerprint));
+        }
+    }
+
+    let mut row_overlap_table = Vec::new();
+    for mut datasets in hash_map.into_values() {
+        datasets.sort_by(|a, b| a.0.cmp(&b.0));
+        for (position, (source, rows)) in datasets.iter().enumerate() {
+            for (other_source, other_rows) in &datasets[position + 1..] {
+                let Some((source_share, other_share)) = content::estimate_row_containment(rows, other_rows) else {
+                    continue;
+                };
+                let exact = content::is_complete(rows) && content::is_complete(other_rows);
+                // Shared rows are bounded by the smaller dataset, so they are counted
+                // from its share
+                let shared_rows = if rows.distinct_rows <= other_rows.distinct_rows {
+                    source_share * rows.distinct_rows as f64
+                } else {
+                    other_share * other_rows.distinct_rows as f64
+                }
+                .round() as usize;
+
+                let contained_share = if exact { 1.0 } else { ROW_CONTAINED_SHARE };
+                // Two datasets that each seem to hold the other's rows are only identical
+                // when their distinct row counts agree; counts estimated from a sketch
+                // agree whenever the sketches do
+                let relationship = match (source_share >= contained_share, other_share >= contained_share) {
+                    (true, true) => match rows.distinct_rows.cmp(&other_rows.distinct_rows) {
+                        Ordering::Equal => "identical",
+                        Ordering::Greater => "superset",
+                        Ordering::Less => "subset",
+                    },
+                    (false, true) => "superset",
+                    (true, false) => "subset",
+                    (false, false) if source_share.min(other_share) >= ROW_NEAR_DUPLICATE_SHARE => "near_duplicate",
+                    (false, false) => continue,
+                };
+
+                row_overlap_table.push(RowOverlapEntry {
+                    source: source.clone(),
+                    other_source: other_source.clone(),
+                    relationship: relationship.to_string(),
+                    shared_rows,
+                    source_rows: rows.distinct_rows,
+                    other_rows: other_rows.distinct_rows,
+                    source_shared_percent: round_percent(source_share),
+                    other_shared_percent: round_percent(other_share),
+                    estimated: !exact,
+                });
+            }
+        }
+    }
+
+    row_overlap_table.sort_by(|a, b| a.source.cmp(&b.source).then_with(|| a.other_source.cmp(&b.other_source)));
+    row_overlap_table
+}
+
+fn round_percent(share: f64) -> f64 {
+    (share * 1000.0).round() / 10.0
+}
+
+/// Groups CSV files and Excel sheets by their normalised content hash, so copies of the
+/// same data saved in different formats are found even though their bytes differ.
+fn build_cross_format_duplicates(directories: &[DirectoryEntry]) -> Vec<CrossFormatDuplicateEntry> {
+    let mut hash_map: HashMap<String, (Vec<String>, Vec<String>)> = HashMap::new(); // (formats, sources)
+    let mut add = |content_hash: &Option<String>, format: &str, source: String| {
+        if let Some(content_hash) = content_hash {
+            let (formats, sources) = hash_map.entry(content_hash.clone()).or_default();
+            if !formats.iter().any(|seen| seen == format) {
+                formats.push(format.to_string());
+            }
+            sources.push(source);
+        }
+    };
+
+    for (file_path, file_details) in collect_files(directories) {
+        if let Some(csv_meta) = &file_details.csv_metadata {
+            add(&csv_meta.content_hash, "csv", file_path.clone());
+        }
+        if let Some(excel_meta) = &file_details.excel_metadata {
+            for sheet in &excel_meta.sheets {
+                add(&sheet.content_hash, "excel", format!("{} ({})", file_path, sheet.sheet_name));
+            }
+        }
+    }
+
+    // Only hashes with multiple sources, as for the CRC32 table
+    let mut duplicates: Vec<CrossFormatDuplicateEntry> = hash_map
+        .into_iter()
+        .filter(|(_, (_, sources))| sources.len() > 1)
+        .map(|(content_hash, (mut formats, mut sources))| {
+            formats.sort();
+            sources.sort();
+            CrossFormatDuplicateEntry { content_hash, formats, sources }
+        })
+        .collect();
+
+    duplicates.sort_by(|a, b| a.content_hash.cmp(&b.content_hash));
+    duplicates
+}
+
+/// Indexes every normalised column name (the form the similarity hash uses, or the
+/// canonical name when a synonym dictionary was applied) to the datasets containing it.
+fn build_column_index(directories: &[DirectoryEntry]) -> Vec<ColumnIndexEntry> {
+    let mut index: HashMap<String, (Vec<String>, Vec<String>)> = HashMap::new(); // (spellings, sources)
+    // (normalised column, source) pairs already listed
+    let mut listed: HashSet<(String, String)> = HashSet::new();
+
+    for column_set in collect_column_sets(directories) {
+        let source = column_set.source;
+        for (column, matching_column) in column_set.columns.iter().zip(column_set.matching_columns) {
+            let normalised = normalise_column_name(matching_column);
+            if normalised.is_empty() {
+                continue;
+            }
+            // A dataset holding two spellings of the same column is listed once
+            let new_source = listed.insert((normalised.clone(), source.clone()));
+            let (spellings, sources) = index.entry(normalised).or_default();
+            if !spellings.contains(column) {
+                spellings.push(column.clone());
+            }
+            if new_source {
+                sources.push(source.clone());
+            }
+        }
+    }
+
+    let mut column_index: Vec<ColumnIndexEntry> = index
+        .into_iter()
+        .map(|(column, (mut spellings, sources))| {
+            spellings.sort();
+            ColumnIndexEntry {
+                column,
+                count: sources.len(),
+                spellings,
+                sources,
+            }
+        })
+        .collect();
+
+    column_index.sort_by(|a, b| a.column.cmp(&b.column));
+    column_index
+}
+
+// Up to this many column sets every pair is compared; above it identical sets are merged
+// and, if more distinct sets remain, MinHash LSH picks the candidate pairs, since
+// all-pairs comparison grows quadratically
+const FUZZY_EXHAUSTIVE_LIMIT: usize = 500;
+
+fn build_fuzzy_similarity_groups(
+    directories: &[DirectoryEntry],
+    threshold: f64,
+    clustering: FuzzyClustering,
+) -> Vec<FuzzySimilarityGroup> {
+    // Collect all column sets with their sources, and the columns of each that can be
+    // matched on content when fingerprints were taken
+    let mut column_sets: Vec<(Vec<String>, String)> = Vec::new();
+    let mut profiles: Vec<content::ContentProfile> = Vec::new();
+    for column_set in collect_column_sets(directories) {
+        column_sets.push((column_set.matching_columns.to_vec(), column_set.source));
+        profiles.push(content::content_profile(column_set.content_fingerprints));
+    }
+
+    if column_sets.len() < 2 {
+        return Vec::new();
+    }
+
+    // Identical column sets always score 1.0 against each other and the same against
+    // everything else, so on large scans each distinct set is scored once for all of its
+    // sources. Small scans keep every set apart, so their groups match a full comparison
+    let merge_identical = column_sets.len() > FUZZY_EXHAUSTIVE_LIMIT;
+    let mut distinct_index: HashMap<Vec<&str>, usize> = HashMap::new();
+    let mut distinct_sets: Vec<(&[String], Vec<usize>)> = Vec::new();
+    let mut distinct_of: Vec<usize> = Vec::new();
+    for (index, (columns, _)) in column_sets.iter().enumerate() {
+        let distinct = if merge_identical {
+            let mut key: Vec<&str> = columns.iter().map(|column| column.as_str()).collect();
+            key.sort_unstable();
+            *distinct_index.entry(key).or_insert_with(|| {
+                distinct_sets.push((columns.as_slice(), Vec::new()));
+                distinct_sets.len() - 1
+            })
+        } else {
+            distinct_sets.push((columns.as_slice(), Vec::new()));
+            distinct_sets.len() - 1
+        };
+        distinct_sets[distinct].1.push(index);
+        distinct_of.push(distinct);
+    }
+
+    // Content is compared per source, since sets with the same columns can hold different
+    // data; a pair of distinct sets takes the best content score between their sources
+    let mut content_matches: HashMap<(usize, usize), content::ColumnMatches> = HashMap::new();
+    let mut content_scores: HashMap<(usize, usize), f64> = HashMap::new();
+    let mut content_partners: HashMap<usize, Vec<usize>> = HashMap::new();
+    if profiles.iter().any(|profile| !profile.is_empty()) {
+        for (a, b) in content::candidate_pairs(&profiles) {
+            let Some((score, matches)) = content::compare(&profiles[a], &profiles[b]) else {
+                continue;
+            };
+            let (i, j) = (distinct_of[a].min(distinct_of[b]), distinct_of[a].max(distinct_of[b]));
+            if i != j {
+                let best = content_scores.entry((i, j)).or_insert_with(|| {
+                    content_partners.entry(i).or_default().push(j);
+                    0.0
+                });
+                *best = best.max(score);
+            }
+            content_matches.insert((a, b), matches);
+        }
+    }
+    let content_score = |i: usize, j: usize| content_scores.get(&(i, j)).copied().unwrap_or(0.0);
+
+    let lsh_index = (distinct_sets.len() > FUZZY_EXHAUSTIVE_LIMIT).then(|| {
+        let mut index = minhash::LshIndex::default();
+        for (columns, _) in &distinct_sets {
+            let tokens = minhash::column_tokens(columns);
+            index.insert(&minhash::signature(tokens.iter().map(|token| token.as_str())));
+        }
+        index
+    });
+
+    // Only LSH candidates, and sets whose content matched, are scored on large scans
+    let candidates = |i: usize| -> Vec<usize> {
+        let mut candidates: Vec<usize> = match &lsh_index {
+            Some(index) => index.candidates(i).into_iter().filter(|&j| j > i).collect(),
+            None => ((i + 1)..distinct_sets.len()).collect(),
+        };
+        if let Some(partners) = content_partners.get(&i) {
+            candidates.extend(partners);
+            candidates.sort_unstable();
+            candidates.dedup();
+        }
+        candidates
+    };
+
+    // Symmetric scores of every pair compared, reused for the group statistics
+    let mut scored_pairs: HashMap<(usize, usize), f64> = HashMap::new();
+    let clusters: Vec<Vec<usize>> = match clustering {
+        FuzzyClustering::Components => {
+            let mut parents: Vec<usize> = (0..distinct_sets.len()).collect();
+            for i in 0..distinct_sets.len() {
+                for j in candidates(i) {
+                    let similarity = pair_similarity(distinct_sets[i].0, distinct_sets[j].0).max(content_score(i, j));
+                    scored_pairs.insert((i, j), similarity);
+                    if similarity >= threshold {
+                        let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                        parents[root_i.max(root_j)] = root_i.min(root_j);
+                    }
+                }
+            }
+
+            let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+            for i in 0..distinct_sets.len() {
+                let root = find_root(&mut parents, i);
+                components.entry(root).or_default().push(i);
+            }
+            components.into_values().collect()
+        }
+        FuzzyClustering::Greedy => {
+            let mut clusters = Vec::new();
+            let mut used_indices: Vec<bool> = vec![false; distinct_sets.len()];
+            for i in 0..distinct_sets.len() {
+                if used_indices[i] {
+                    continue;
+                }
+                used_indices[i] = true;
+
+                // Later sets are compared against the seed only
+                let mut cluster = vec![i];
+                for j in candidates(i) {
+                    if used_indices[j] {
+                        continue;
+                    }
+                    let content = content_score(i, j);
+                    let similarity = calculate_column_set_similarity(distinct_sets[i].0, distinct_sets[j].0).max(content);
+                    scored_pairs.insert((i, j), pair_similarity(distinct_sets[i].0, distinct_sets[j].0).max(content));
+                    if similarity >= threshold {
+                        cluster.push(j);
+                        used_indices[j] = true;
+                    }
+                }
+                clusters.push(cluster);
+            }
+            clusters
+        }
+    };
+
+    let mut groups: Vec<FuzzySimilarityGroup> = clusters
+        .into_iter()
+        .filter(|cluster| cluster.iter().map(|&i| distinct_sets[i].1.len()).sum::<usize>() > 1)
+        .map(|mut cluster| {
+            cluster.sort_unstable();
+            summarise_fuzzy_group(&cluster, &distinct_sets, &column_sets, &scored_pairs, &profiles, &content_matches)
+        })
         .collect();
 
-    crc32_table.sort_by(|a, b| a.hash.cmp(&b.hash));
-    crc32_table
-}
-
-fn build_fuzzy_similarity_groups(directories: &[DirectoryEntry], threshold: f64) -> Vec<FuzzySimilarityGroup> {
-    // Collect all column sets with their sources
-    let mut column_sets: Vec<(Vec<String>, String)> = Vec::new();
+    // Groups are listed by their first source; IDs come from their columns, so a group
+    // keeps its ID when unrelated files are added to the scan
+    groups.sort_by(|a, b| a.sources.cmp(&b.sources));
+    disambiguate_group_ids(&mut groups);
 
-    for dir_entry in directories {
-        for file_details in &dir_entry.files {
-            let file_path = format!("{}/{}", dir_entry.path, file_details.name);
+    groups
+}
 
-            // Collect CSV columns
-            if let Some(csv_meta) = &file_details.csv_metadata {
-                column_sets.push((csv_meta.columns.clone(), file_path.clone()));
-            }
+/// Suffixes the IDs of groups that share a centroid with a hash of each group's own sources,
+/// so that no group's ID depends on the order of the others.
+fn disambiguate_group_ids(groups: &mut [FuzzySimilarityGroup]) {
+    let mut id_counts: HashMap<String, usize> = HashMap::new();
+    for group in groups.iter() {
+        *id_counts.entry(group.group_id.clone()).or_insert(0) += 1;
+    }
+    for group in groups.iter_mut() {
+        if id_counts[&group.group_id] > 1 {
+            let sources_hash = twox_hash::xxh3::hash64(group.sources.join("\n").as_bytes());
+            group.group_id = format!("{}-{:08x}", group.group_id, sources_hash >> 32);
+        }
+    }
+}
 
-            // Collect Excel columns (per sheet)
-            if let Some(excel_meta) = &file_details.excel_metadata {
-                for sheet in &excel_meta.sheets {
-                    let sheet_source = format!("{} ({})", file_path, sheet.sheet_name);
-                    column_sets.push((sheet.columns.clone(), sheet_source));
-                }
+/// Builds the reported group for a cluster of distinct column sets: similarity statistics
+/// over member pairs, the centroid (columns held by at least half of the members) and
+/// each member's similarity to it.
+fn summarise_fuzzy_group(
+    cluster: &[usize],
+    distinct_sets: &[(&[String], Vec<usize>)],
+    column_sets: &[(Vec<String>, String)],
+    scored_pairs: &HashMap<(usize, usize), f64>,
+    profiles: &[content::ContentProfile],
+    content_matches: &HashMap<(usize, usize), content::ColumnMatches>,
+) -> FuzzySimilarityGroup {
+    // Pairs of identical sources score 1.0; other pairs count once per source pairing.
+    // Pairs that LSH never compared are left out
+    let (mut min, mut max, mut total, mut pairs) = (f64::MAX, f64::MIN, 0.0, 0usize);
+    let mut add_pairs = |similarity: f64, count: usize| {
+        if count > 0 {
+            min = min.min(similarity);
+            max = max.max(similarity);
+            total += similarity * count as f64;
+            pairs += count;
+        }
+    };
+    // Distinct sets are numbered by their position in the cluster
+    let mut group_pairs = Vec::new();
+    let mut set_of: HashMap<usize, usize> = HashMap::new();
+    for (position, &i) in cluster.iter().enumerate() {
+        let copies = distinct_sets[i].1.len();
+        add_pairs(1.0, copies * (copies - 1) / 2);
+        for (other_position, &j) in cluster.iter().enumerate().skip(position + 1) {
+            if let Some(&similarity) = scored_pairs.get(&(i, j)) {
+                add_pairs(similarity, copies * distinct_sets[j].1.len());
+                group_pairs.push((position, other_position, similarity));
             }
         }
+        set_of.extend(distinct_sets[i].1.iter().map(|&index| (index, position)));
     }
 
-    if column_sets.len() < 2 {
-        return Vec::new();
+    let mut member_indices: Vec<usize> = cluster.iter().flat_map(|&i| distinct_sets[i].1.iter().copied()).collect();
+    member_indices.sort_by(|&a, &b| column_sets[a].1.cmp(&column_sets[b].1));
+
+    let mut representative_columns: HashSet<&str> = HashSet::new();
+    let mut column_counts: HashMap<&str, usize> = HashMap::new();
+    for &index in &member_indices {
+        representative_columns.extend(column_sets[index].0.iter().map(|column| column.as_str()));
+        let mut columns: Vec<&str> = column_sets[index].0.iter().map(|column| column.as_str()).collect();
+        columns.sort_unstable();
+        columns.dedup();
+        for column in columns {
+            *column_counts.entry(column).or_insert(0) += 1;
+        }
     }
+    let mut representative_columns: Vec<String> =
+        representative_columns.into_iter().map(|column| column.to_string()).collect();
+    representative_columns.sort();
 
-    // Group similar column sets using clustering approach
-    let mut groups: Vec<FuzzySimilarityGroup> = Vec::new();
-    let mut used_indices: Vec<bool> = vec![false; column_sets.len()];
-    let mut group_id = 0;
+    let mut centroid_columns: Vec<String> = column_counts
+        .into_iter()
+        .filter(|(_, count)| count * 2 >= member_indices.len())
+        .map(|(column, _)| column.to_string())
+        .collect();
+    centroid_columns.sort();
 
-    for i in 0..column_sets.len() {
-        if used_indices[i] {
-            continue;
-        }
+    let centroid_similarities = member_indices
+        .iter()
+        .map(|&index| round_similarity(pair_similarity(&column_sets[index].0, &centroid_columns)))
+        .collect();
 
-        let mut group_sources = vec![column_sets[i].1.clone()];
-        let mut group_columns = column_sets[i].0.clone();
-        used_indices[i] = true;
+    let member_sets = member_indices.iter().map(|index| set_of[index]).collect();
+    let members: Vec<(&str, &[String])> = member_indices
+        .iter()
+        .map(|&index| (column_sets[index].1.as_str(), column_sets[index].0.as_slice()))
+        .collect();
+    let schema_diff = schema_diff::build_schema_diff(&members);
 
-        // Find similar column sets
-        for j in (i + 1)..column_sets.len() {
-            if used_indices[j] {
+    // Columns whose values match but whose names differ, between each pair of members
+    let mut column_correspondences = Vec::new();
+    for (position, &a) in member_indices.iter().enumerate() {
+        for &b in &member_indices[position + 1..] {
+            let Some(matches) = content_matches.get(&(a.min(b), a.max(b))) else {
                 continue;
-            }
-
-            let similarity = calculate_column_set_similarity(&column_sets[i].0, &column_sets[j].0);
-            if similarity >= threshold {
-                group_sources.push(column_sets[j].1.clone());
-                // Merge columns (union for representative set)
-                for col in &column_sets[j].0 {
-                    if !group_columns.contains(col) {
-                        group_columns.push(col.clone());
-                    }
+            };
+            for &(column_low, column_high, similarity) in matches {
+                let (column_a, column_b) = if a < b {
+                    (&profiles[a][column_low].0, &profiles[b][column_high].0)
+                } else {
+                    (&profiles[a][column_high].0, &profiles[b][column_low].0)
+                };
+                if normalise_column_name(column_a) != normalise_column_name(column_b) {
+                    column_correspondences.push(ColumnCorrespondence {
+                        source: column_sets[a].1.clone(),
+                        column: column_a.clone(),
+                        other_source: column_sets[b].1.clone(),
+                        other_column: column_b.clone(),
+                        similarity: round_similarity(similarity),
+                    });
                 }
-                used_indices[j] = true;
             }
         }
+    }
 
-        // Only create groups with multiple sources
-        if group_sources.len() > 1 {
-            group_columns.sort();
-            groups.push(FuzzySimilarityGroup {
-                group_id,
-                similarity_score: threshold,
-                representative_columns: group_columns,
-                sources: group_sources,
-            });
-            group_id += 1;
-        }
+    FuzzySimilarityGroup {
+        group_id: calculate_column_similarity_hash(&centroid_columns)[..8].to_string(),
+        similarity_score: round_similarity(if pairs > 0 { total / pairs as f64 } else { 0.0 }),
+        min_similarity: round_similarity(if pairs > 0 { min } else { 0.0 }),
+        max_similarity: round_similarity(if pairs > 0 { max } else { 0.0 }),
+        representative_columns,
+        centroid_columns,
+        sources: member_indices.iter().map(|&index| column_sets[index].1.clone()).collect(),
+        centroid_similarities,
+        schema_diff,
+        column_correspondences,
+        member_sets,
+        scored_pairs: group_pairs,
+    }
+}
+
+/// Scores a pair in both directions and keeps the lower score, so the result does not
+/// depend on which set was found first.
+fn pair_similarity(set1: &[String], set2: &[String]) -> f64 {
+    calculate_column_set_similarity(set1, set2).min(calculate_column_set_similarity(set2, set1))
+}
+
+fn find_root(parents: &mut [usize], mut item: usize) -> usize {
+    while parents[item] != item {
+        parents[item] = parents[parents[item]];
+        item = parents[item];
     }
+    item
+}
 
-    groups
+fn round_similarity(similarity: f64) -> f64 {
+    (similarity * 1000.0).round() / 1000.0
 }
 
+// Jaro-Winkler score above which two column names count as the same column
+const COLUMN_MATCH_THRESHOLD: f64 = 0.8;
+
 fn calculate_column_set_similarity(set1: &[String], set2: &[String]) -> f64 {
     if set1.is_empty() && set2.is_empty() {
         return 1.0;
@@ -394,7 +1630,7 @@ fn calculate_column_set_similarity(set1: &[String], set2: &[String]) -> f64 {
                 best_match = similarity;
             }
         }
-        if best_match > 0.8 {  // Individual column similarity threshold
+        if best_match > COLUMN_MATCH_THRESHOLD {
             matches += 1;
         }
     }
@@ -408,18 +1644,34 @@ fn calculate_column_set_similarity(set1: &[String], set2: &[String]) -> f64 {
     }
 }
 
-fn is_supported_file_type(path: &Path) -> bool {
-    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+// Files up to this size get a CRC32 hash; larger files only report their size
+const MAX_HASH_SIZE: u64 = 128 * 1024; // 128KB
+
+fn is_supported_file_type(path: &Path, options: &ScanOptions) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|name| is_supported_file_name(name, options))
+        .unwrap_or(false)
+}
+
+fn is_supported_file_name(name: &str, options: &ScanOptions) -> bool {
+    if options.scan_archives && archive::archive_format(name).is_some() {
+        return true;
+    }
+
+    if let Some(extension) = Path::new(name).extension().and_then(|e| e.to_str()) {
         matches!(
             extension.to_lowercase().as_str(),
-            "csv" | "xlsx" | "xls" | "xlsm" | "xlsb" | "pdf" | "docx" | "eml"
+            "csv" | "tsv" | "tab" | "txt" | "dat" | "xlsx" | "xls" | "xlsm" | "xlsb" | "ods" | "json"
+                | "ndjson" | "jsonl" | "parquet" | "feather" | "arrow" | "avro" | "sav" | "zsav" | "dta" | "sas7bdat" | "sqlite" | "sqlite3" | "db" | "mdb"
+                | "accdb" | "pdf" | "jpg" | "jpeg" | "png" | "tif" | "tiff" | "docx" | "pptx" | "doc" | "ppt" | "eml" | "msg"
         )
     } else {
         false
     }
 }
 
-fn process_file(path: &Path, enable_hash: bool, max_rows: usize) -> Result<FileDetails> {
+fn process_file(path: &Path, options: &ScanOptions) -> Result<FileDetails> {
     let file_name = path
         .file_name()
         .and_then(|n| n.to_str())
@@ -432,8 +1684,7 @@ fn process_file(path: &Path, enable_hash: bool, max_rows: usize) -> Result<FileD
     let file_size = metadata.len();
 
     // Calculate hash for files <= 128KB, otherwise just store file size
-    const MAX_HASH_SIZE: u64 = 128 * 1024; // 128KB
-    let (hash_value, size_value) = if enable_hash && file_size <= MAX_HASH_SIZE {
+    let (hash_value, size_value) = if options.enable_hash && file_size <= MAX_HASH_SIZE {
         (Some(calculate_crc32(path)?), None)
     } else {
         (None, Some(file_size))
@@ -445,37 +1696,168 @@ fn process_file(path: &Path, enable_hash: bool, max_rows: usize) -> Result<FileD
         .unwrap_or("")
         .to_lowercase();
 
-    let mut file_details = FileDetails {
-        name: redacted_name,
-        created,
-        file_type: None,
-        file_size: size_value,
-        crc32_hash: hash_value,
-        csv_metadata: None,
-        excel_metadata: None,
-    };
+    let mut file_details = new_file_details(redacted_name, created, size_value, hash_value);
+    let mut budget = archive::ArchiveBudget::new(options.archive_max_total_size);
+
+    if options.scan_archives {
+        if let Some(format) = archive::archive_format(&file_name) {
+            file_details.file_type = Some("archive".to_string());
+            file_details.archive_metadata =
+                archive::extract_archive_metadata(path, format, options, &mut budget).ok();
+            return Ok(file_details);
+        }
+    }
+
+    // Encrypted files cannot be parsed; flag them so they are not mistaken for corrupt files
+    if let Some(protection_type) = protection::detect_protection(path) {
+        file_details.encrypted = Some(true);
+        file_details.protection_type = Some(protection_type);
+    }
 
     match extension.as_str() {
         "csv" => {
             file_details.file_type = Some("csv".to_string());
-            if let Ok(csv_meta) = extract_csv_metadata(path, max_rows) {
+            if let Ok(csv_meta) = extract_csv_metadata(path, options.max_rows, options.fingerprints) {
                 file_details.csv_metadata = Some(csv_meta);
             }
         }
-        "xlsx" | "xls" | "xlsm" | "xlsb" => {
+        "tsv" | "tab" | "txt" | "dat" => {
+            // Text that does not sniff as delimited or fixed-width is not a dataset
+            let Some(csv_meta) = dialect::extract_text_metadata(File::open(path)?, &extension, options.max_rows, options.fingerprints)?
+            else {
+                anyhow::bail!("Not a tabular text file: {:?}", path);
+            };
+            file_details.file_type = Some("csv".to_string());
+            file_details.csv_metadata = Some(csv_meta);
+        }
+        "xlsx" | "xls" | "xlsm" | "xlsb" | "ods" => {
             file_details.file_type = Some("excel".to_string());
-            if let Ok(excel_meta) = extract_excel_metadata(path, max_rows) {
+            if let Ok(mut excel_meta) = extract_excel_metadata(path, options.max_rows, options.fingerprints) {
+                // Only the OOXML formats carry OOXML parts; .xls is an OLE2 container
+                // and .ods an OpenDocument package
+                if extension == "xls" {
+                    if let Ok(mut compound) = cfb::open(path) {
+                        excel_meta.properties = ole::extract_ole_metadata(&mut compound, options.person_names)
+                            .map(|ole_meta| ole_meta.properties);
+                    }
+                } else if extension != "ods" {
+                    if let Ok(mut archive) = ooxml::open_package(path) {
+                        ooxml::annotate_workbook(&mut archive, &mut excel_meta, options.person_names);
+                    }
+                }
                 file_details.excel_metadata = Some(excel_meta);
             }
         }
+        "json" | "ndjson" | "jsonl" => {
+            file_details.file_type = Some("json".to_string());
+            if let Ok(json_meta) = File::open(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|file| json::extract_json_metadata(file, &extension, options.max_rows, options.fingerprints))
+            {
+                file_details.json_metadata = Some(json_meta);
+            }
+        }
+        "parquet" | "feather" | "arrow" | "avro" => {
+            let format = columnar::columnar_format(&extension).unwrap_or_default();
+            file_details.file_type = Some(format.to_string());
+            if let Ok(columnar_meta) = File::open(path)
+                .map(BufReader::new)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut file| columnar::extract_columnar_metadata(&mut file, format))
+            {
+                file_details.columnar_metadata = Some(columnar_meta);
+            }
+        }
+        "sav" | "zsav" | "dta" | "sas7bdat" => {
+            let format = statistical::statistical_format(&extension).unwrap_or_default();
+            file_details.file_type = Some(format.to_string());
+            if let Ok(statistical_meta) = File::open(path)
+                .map(BufReader::new)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut file| statistical::extract_statistical_metadata(&mut file, format))
+            {
+                file_details.statistical_metadata = Some(statistical_meta);
+            }
+        }
+        "sqlite" | "sqlite3" | "db" | "mdb" | "accdb" => {
+            match database::detect_database(&database::read_header(path)?) {
+                Some(mut database_meta) => {
+                    if database_meta.format == "sqlite" {
+                        database_meta.tables =
+                            database::extract_sqlite_tables(path, options.max_rows).unwrap_or_default();
+                    }
+                    file_details.file_type = Some(database_meta.format.clone());
+                    file_details.database_metadata = Some(database_meta);
+                }
+                // .db is also used for non-database files such as Thumbs.db caches, which
+                // are listed without a file type
+                None if extension == "db" => {}
+                None => file_details.file_type = database::database_format(&extension).map(|f| f.to_string()),
+            }
+        }
         "pdf" => {
             file_details.file_type = Some("pdf".to_string());
+            match pdf::extract_pdf_metadata(path, options.person_names) {
+                Ok(pdf_meta) => {
+                    file_details.encrypted = pdf_meta.encrypted.then_some(true);
+                    file_details.protection_type = pdf_meta.encrypted.then(|| "password".to_string());
+                    file_details.pdf_metadata = Some(pdf_meta);
+                }
+                // An encrypted file lopdf cannot load still declares its encryption in the trailer
+                Err(_) => {
+                    if File::open(path).is_ok_and(|mut file| pdf::trailer_declares_encryption(&mut file)) {
+                        file_details.encrypted = Some(true);
+                        file_details.protection_type = Some("password".to_string());
+                    }
+                }
+            }
+        }
+        "jpg" | "jpeg" | "png" | "tif" | "tiff" => {
+            let format = image::image_format(&extension).unwrap_or_default();
+            file_details.file_type = Some("image".to_string());
+            if let Ok(image_meta) = File::open(path)
+                .map(BufReader::new)
+                .map_err(anyhow::Error::from)
+                .and_then(|mut file| image::extract_image_metadata(&mut file, format))
+            {
+                file_details.image_metadata = Some(image_meta);
+            }
         }
         "docx" => {
             file_details.file_type = Some("docx".to_string());
+            if let Ok(mut archive) = ooxml::open_package(path) {
+                file_details.docx_metadata = ooxml::extract_docx_metadata(&mut archive, options.person_names).ok();
+                file_details.embedded_files =
+                    ooxml::extract_embedded_files(&mut archive, "word/embeddings/", options, 0, &mut budget);
+            }
+        }
+        "pptx" => {
+            file_details.file_type = Some("pptx".to_string());
+            if let Ok(mut archive) = ooxml::open_package(path) {
+                file_details.pptx_metadata = ooxml::extract_pptx_metadata(&mut archive, options.person_names).ok();
+                file_details.embedded_files =
+                    ooxml::extract_embedded_files(&mut archive, "ppt/embeddings/", options, 0, &mut budget);
+            }
+        }
+        "doc" | "ppt" => {
+            file_details.file_type = Some(extension.clone());
+            if let Ok(mut compound) = cfb::open(path) {
+                file_details.ole_metadata = ole::extract_ole_metadata(&mut compound, options.person_names);
+            }
         }
         "eml" => {
             file_details.file_type = Some("eml".to_string());
+            if let Ok(bytes) = fs::read(path) {
+                file_details.eml_metadata =
+                    email::extract_eml_metadata(&bytes, options, 0, &mut budget);
+            }
+        }
+        "msg" => {
+            file_details.file_type = Some("msg".to_string());
+            if let Ok(mut compound) = cfb::open(path) {
+                file_details.msg_metadata =
+                    msg::extract_msg_metadata(&mut compound, options, 0, &mut budget).ok();
+            }
         }
         _ => {}
     }
@@ -483,6 +1865,214 @@ fn process_file(path: &Path, enable_hash: bool, max_rows: usize) -> Result<FileD
     Ok(file_details)
 }
 
+/// Builds `FileDetails` for a file held in memory, such as an email attachment or archive member.
+/// `created` comes from the container because embedded files have no filesystem time.
+fn process_embedded_file(
+    name: &str,
+    bytes: &[u8],
+    created: &str,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut archive::ArchiveBudget,
+) -> FileDetails {
+    let (hash_value, size_value) = if options.enable_hash && bytes.len() as u64 <= MAX_HASH_SIZE {
+        let mut hasher = Hasher::new();
+        hasher.update(bytes);
+        (Some(format!("{:08x}", hasher.finalize())), None)
+    } else {
+        (None, Some(bytes.len() as u64))
+    };
+
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let mut file_details = new_file_details(
+        redact_nhs_numbers(name),
+        created.to_string(),
+        size_value,
+        hash_value,
+    );
+
+    if options.scan_archives {
+        if let Some(format) = archive::archive_format(name) {
+            file_details.file_type = Some("archive".to_string());
+            file_details.archive_metadata =
+                archive::extract_archive_metadata_from_bytes(bytes, format, options, depth, budget)
+                    .ok();
+            return file_details;
+        }
+    }
+
+    if extension != "pdf" {
+        if let Ok(mut compound) = cfb::CompoundFile::open(Cursor::new(bytes)) {
+            if let Some(protection_type) = protection::detect_ole_protection(&mut compound) {
+                file_details.encrypted = Some(true);
+                file_details.protection_type = Some(protection_type);
+            }
+        }
+    }
+
+    match extension.as_str() {
+        "csv" => {
+            file_details.file_type = Some("csv".to_string());
+            if let Ok(csv_meta) = extract_csv_metadata_from_reader(bytes, options.max_rows, options.fingerprints) {
+                file_details.csv_metadata = Some(csv_meta);
+            }
+        }
+        "tsv" | "tab" | "txt" | "dat" => {
+            if let Ok(Some(csv_meta)) = dialect::extract_text_metadata(bytes, &extension, options.max_rows, options.fingerprints) {
+                file_details.file_type = Some("csv".to_string());
+                file_details.csv_metadata = Some(csv_meta);
+            }
+        }
+        "xlsx" | "xls" | "xlsm" | "xlsb" | "ods" => {
+            file_details.file_type = Some("excel".to_string());
+            if let Ok(mut workbook) = open_workbook_auto_from_rs(Cursor::new(bytes)) {
+                let mut excel_meta = extract_workbook_metadata(&mut workbook, options.max_rows, options.fingerprints);
+                if extension == "xls" {
+                    if let Ok(mut compound) = cfb::CompoundFile::open(Cursor::new(bytes)) {
+                        excel_meta.properties = ole::extract_ole_metadata(&mut compound, options.person_names)
+                            .map(|ole_meta| ole_meta.properties);
+                    }
+                } else if extension != "ods" {
+                    if let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(bytes)) {
+                        ooxml::annotate_workbook(&mut archive, &mut excel_meta, options.person_names);
+                    }
+                }
+                file_details.excel_metadata = Some(excel_meta);
+            }
+        }
+        "json" | "ndjson" | "jsonl" => {
+            file_details.file_type = Some("json".to_string());
+            if let Ok(json_meta) = json::extract_json_metadata(bytes, &extension, options.max_rows, options.fingerprints) {
+                file_details.json_metadata = Some(json_meta);
+            }
+        }
+        "parquet" | "feather" | "arrow" | "avro" => {
+            let format = columnar::columnar_format(&extension).unwrap_or_default();
+            file_details.file_type = Some(format.to_string());
+            if let Ok(columnar_meta) = columnar::extract_columnar_metadata(&mut Cursor::new(bytes), format) {
+                file_details.columnar_metadata = Some(columnar_meta);
+            }
+        }
+        "sav" | "zsav" | "dta" | "sas7bdat" => {
+            let format = statistical::statistical_format(&extension).unwrap_or_default();
+            file_details.file_type = Some(format.to_string());
+            if let Ok(statistical_meta) =
+                statistical::extract_statistical_metadata(&mut Cursor::new(bytes), format)
+            {
+                file_details.statistical_metadata = Some(statistical_meta);
+            }
+        }
+        "sqlite" | "sqlite3" | "db" | "mdb" | "accdb" => {
+            // Tables are only listed for files on disk; SQLite cannot open a byte buffer
+            // without handing it memory it allocated itself
+            match database::detect_database(bytes) {
+                Some(database_meta) => {
+                    file_details.file_type = Some(database_meta.format.clone());
+                    file_details.database_metadata = Some(database_meta);
+                }
+                None if extension == "db" => {}
+                None => file_details.file_type = database::database_format(&extension).map(|f| f.to_string()),
+            }
+        }
+        "pdf" => {
+            file_details.file_type = Some("pdf".to_string());
+            match pdf::extract_pdf_metadata_from_bytes(bytes, options.person_names) {
+                Ok(pdf_meta) => {
+                    file_details.encrypted = pdf_meta.encrypted.then_some(true);
+                    file_details.protection_type = pdf_meta.encrypted.then(|| "password".to_string());
+                    file_details.pdf_metadata = Some(pdf_meta);
+                }
+                Err(_) => {
+                    if pdf::trailer_declares_encryption(&mut Cursor::new(bytes)) {
+                        file_details.encrypted = Some(true);
+                        file_details.protection_type = Some("password".to_string());
+                    }
+                }
+            }
+        }
+        "jpg" | "jpeg" | "png" | "tif" | "tiff" => {
+            let format = image::image_format(&extension).unwrap_or_default();
+            file_details.file_type = Some("image".to_string());
+            if let Ok(image_meta) = image::extract_image_metadata(&mut Cursor::new(bytes), format) {
+                file_details.image_metadata = Some(image_meta);
+            }
+        }
+        "docx" => {
+            file_details.file_type = Some("docx".to_string());
+            if let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(bytes)) {
+                file_details.docx_metadata = ooxml::extract_docx_metadata(&mut archive, options.person_names).ok();
+                file_details.embedded_files =
+                    ooxml::extract_embedded_files(&mut archive, "word/embeddings/", options, depth, budget);
+            }
+        }
+        "pptx" => {
+            file_details.file_type = Some("pptx".to_string());
+            if let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(bytes)) {
+                file_details.pptx_metadata = ooxml::extract_pptx_metadata(&mut archive, options.person_names).ok();
+                file_details.embedded_files =
+                    ooxml::extract_embedded_files(&mut archive, "ppt/embeddings/", options, depth, budget);
+            }
+        }
+        "doc" | "ppt" => {
+            file_details.file_type = Some(extension.clone());
+            if let Ok(mut compound) = cfb::CompoundFile::open(Cursor::new(bytes)) {
+                file_details.ole_metadata = ole::extract_ole_metadata(&mut compound, options.person_names);
+            }
+        }
+        "eml" => {
+            file_details.file_type = Some("eml".to_string());
+            file_details.eml_metadata = email::extract_eml_metadata(bytes, options, depth, budget);
+        }
+        "msg" => {
+            file_details.file_type = Some("msg".to_string());
+            if let Ok(mut compound) = cfb::CompoundFile::open(Cursor::new(bytes)) {
+                file_details.msg_metadata =
+                    msg::extract_msg_metadata(&mut compound, options, depth, budget).ok();
+            }
+        }
+        _ => {}
+    }
+
+    file_details
+}
+
+fn new_file_details(
+    name: String,
+    created: String,
+    file_size: Option<u64>,
+    crc32_hash: Option<String>,
+) -> FileDetails {
+    FileDetails {
+        name,
+        created,
+        file_type: None,
+        file_size,
+        crc32_hash,
+        encrypted: None,
+        protection_type: None,
+        csv_metadata: None,
+        excel_metadata: None,
+        json_metadata: None,
+        columnar_metadata: None,
+        statistical_metadata: None,
+        database_metadata: None,
+        pdf_metadata: None,
+        image_metadata: None,
+        docx_metadata: None,
+        pptx_metadata: None,
+        ole_metadata: None,
+        eml_metadata: None,
+        msg_metadata: None,
+        archive_metadata: None,
+        embedded_files: Vec::new(),
+    }
+}
+
 fn get_creation_time(path: &Path) -> Result<String> {
     let metadata = fs::metadata(path)?;
     let created = metadata
@@ -510,58 +2100,104 @@ fn calculate_crc32(path: &Path) -> Result<String> {
     Ok(format!("{:08x}", hasher.finalize()))
 }
 
-fn calculate_column_similarity_hash(columns: &[String]) -> u32 {
-    // Process column names: lowercase, remove non-alphanumeric, filter empty, sort
+// Version of the column similarity hash scheme reported with each scan: 1 was a CRC32 of
+// the normalised, sorted names; 2 hashes the same names with 64-bit xxh3
+const COLUMN_HASH_VERSION: u32 = 2;
+
+fn calculate_column_similarity_hash(columns: &[String]) -> String {
+    column_similarity_hash(columns, false)
+}
+
+fn column_similarity_hash(columns: &[String], ordered: bool) -> String {
+    // Process column names: lowercase, remove non-alphanumeric, filter empty, sort unless
+    // column order matters
     let mut processed_columns: Vec<String> = columns
         .iter()
-        .map(|col| {
-            col.to_lowercase()
-                .chars()
-                .filter(|c| c.is_alphanumeric())
-                .collect::<String>()
-        })
+        .map(|col| normalise_column_name(col))
         .filter(|col| !col.is_empty())
         .collect();
 
-    processed_columns.sort();
+    if !ordered {
+        processed_columns.sort();
+    }
     let concatenated = processed_columns.join(",");
 
-    let mut hasher = Hasher::new();
-    hasher.update(concatenated.as_bytes());
-    hasher.finalize()
+    format!("{:016x}", twox_hash::xxh3::hash64(concatenated.as_bytes()))
+}
+
+/// Lowercases a column name and drops everything but letters and digits.
+fn normalise_column_name(column: &str) -> String {
+    column
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+fn extract_csv_metadata(path: &Path, max_rows: usize, fingerprints: content::Fingerprints) -> Result<CsvMetadata> {
+    extract_csv_metadata_from_reader(File::open(path)?, max_rows, fingerprints)
 }
 
-fn extract_csv_metadata(path: &Path, max_rows: usize) -> Result<CsvMetadata> {
-    let mut reader = ReaderBuilder::new().has_headers(true).from_path(path)?;
+fn extract_csv_metadata_from_reader<R: Read>(
+    source: R,
+    max_rows: usize,
+    fingerprints: content::Fingerprints,
+) -> Result<CsvMetadata> {
+    let mut reader = ReaderBuilder::new().has_headers(true).from_reader(source);
 
     let headers = reader.headers()?.clone();
     let columns: Vec<String> = headers.iter().map(redact_nhs_numbers).collect();
 
     let mut row_count = 0;
     let mut stopped_at = None;
+    let mut sampler = fingerprints.sampler();
+    let mut hasher = fingerprints.hasher();
+    if let Some(hasher) = &mut hasher {
+        hasher.add_row(headers.iter());
+    }
 
-    for record in reader.records() {
-        if record.is_ok() {
-            row_count += 1;
-            if row_count >= max_rows {
-                stopped_at = Some(row_count);
-                break;
-            }
+    for record in reader.records().flatten() {
+        if let Some(sampler) = &mut sampler {
+            sampler.add_row(record.iter(), &columns);
+        }
+        if let Some(hasher) = &mut hasher {
+            hasher.add_row(record.iter());
+        }
+        row_count += 1;
+        if row_count >= max_rows {
+            stopped_at = Some(row_count);
+            break;
         }
     }
 
     let similarity_hash = calculate_column_similarity_hash(&columns);
+    let (content_fingerprints, row_fingerprint) = sampler.map(|sampler| sampler.finish(&columns)).unwrap_or_default();
+    // A hash of the first rows only would match unrelated files that start the same way
+    let content_hash = hasher.filter(|_| stopped_at.is_none()).and_then(|hasher| hasher.finish());
 
     Ok(CsvMetadata {
         columns,
         row_count,
+        canonical_columns: Vec::new(),
         column_similarity_hash: similarity_hash,
         stopped_row_count_at: stopped_at,
+        dialect: None,
+        content_hash,
+        content_fingerprints,
+        row_fingerprint,
     })
 }
 
-fn extract_excel_metadata(path: &Path, max_rows: usize) -> Result<ExcelMetadata> {
+fn extract_excel_metadata(path: &Path, max_rows: usize, fingerprints: content::Fingerprints) -> Result<ExcelMetadata> {
     let mut workbook = open_workbook_auto(path)?;
+    Ok(extract_workbook_metadata(&mut workbook, max_rows, fingerprints))
+}
+
+fn extract_workbook_metadata<RS: Read + Seek>(
+    workbook: &mut Sheets<RS>,
+    max_rows: usize,
+    fingerprints: content::Fingerprints,
+) -> ExcelMetadata {
     let mut sheets = Vec::new();
 
     for sheet_name in workbook.sheet_names().to_vec() {
@@ -583,17 +2219,61 @@ fn extract_excel_metadata(path: &Path, max_rows: usize) -> Result<ExcelMetadata>
 
             let similarity_hash = calculate_column_similarity_hash(&columns);
 
+            let mut content_fingerprints = Vec::new();
+            let mut row_fingerprint = None;
+            if let Some(mut sampler) = fingerprints.sampler() {
+                for row in range.rows().skip(header_row_idx + 1).take(row_count) {
+                    if sampler.is_full() {
+                        break;
+                    }
+                    sampler.add_row(row.iter().map(cell_text), &columns);
+                }
+                (content_fingerprints, row_fingerprint) = sampler.finish(&columns);
+            }
+
+            // Rows above the detected header are hashed too, as a CSV copy of the sheet
+            // holds them
+            let mut content_hash = None;
+            if let Some(mut hasher) = fingerprints.hasher().filter(|_| stopped_at.is_none()) {
+                for row in range.rows() {
+                    hasher.add_row(row.iter().map(cell_text));
+                }
+                content_hash = hasher.finish();
+            }
+
             sheets.push(SheetMetadata {
                 sheet_name: redact_nhs_numbers(&sheet_name),
                 columns,
                 row_count,
+                canonical_columns: Vec::new(),
                 column_similarity_hash: similarity_hash,
                 stopped_row_count_at: stopped_at,
+                protected: None,
+                content_hash,
+                content_fingerprints,
+                row_fingerprint,
             });
         }
     }
 
-    Ok(ExcelMetadata { sheets })
+    ExcelMetadata {
+        sheets,
+        workbook_protected: None,
+        properties: None,
+    }
+}
+
+/// Formats a cell as a CSV copy of the sheet would hold it: numbers as numbers and dates
+/// and times in ISO 8601 form rather than as Excel serial numbers.
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(date_time) if date_time.is_datetime() => match date_time.as_datetime() {
+            Some(date_time) if date_time.time() == chrono::NaiveTime::MIN => date_time.format("%Y-%m-%d").to_string(),
+            Some(date_time) => date_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => cell.to_string(),
+        },
+        _ => cell.to_string(),
+    }
 }
 
 fn extract_excel_columns_with_header_row(range: &Range<Data>) -> (Vec<String>, usize) {
@@ -642,11 +2322,15 @@ fn extract_excel_columns_with_header_row(range: &Range<Data>) -> (Vec<String>, u
 }
 
 fn redact_nhs_numbers(text: &str) -> String {
+    // Compiled once; this runs for every path, column name and property in the scan
+    static RE_TEN_DIGITS: OnceLock<Regex> = OnceLock::new();
+    static RE_SPACED: OnceLock<Regex> = OnceLock::new();
+
     // Pattern 1: 10 consecutive digits
     // Matches 10 digits that are not part of a longer sequence
-    let re_ten_digits = Regex::new(r"\d{10}").unwrap();
+    let re_ten_digits = RE_TEN_DIGITS.get_or_init(|| Regex::new(r"\d{10}").unwrap());
     // Pattern 2: nnn nnn nnnn format
-    let re_spaced = Regex::new(r"\d{3}\s+\d{3}\s+\d{4}").unwrap();
+    let re_spaced = RE_SPACED.get_or_init(|| Regex::new(r"\d{3}\s+\d{3}\s+\d{4}").unwrap());
 
     let mut result = text.to_string();
 
@@ -680,3 +2364,275 @@ fn redact_nhs_numbers(text: &str) -> String {
     final_result.push_str(&result[last_end..]);
     final_result
 }
+
+fn redact_person_name(name: &str, mode: PersonNameMode) -> String {
+    let name = name.trim();
+    match mode {
+        PersonNameMode::Keep => redact_nhs_numbers(name),
+        PersonNameMode::Redact => "[REDACTED]".to_string(),
+        PersonNameMode::Pseudonymise => pseudonym(name, pseudonym_key()),
+    }
+}
+
+/// HMAC-SHA256 of the lowercased name, truncated to 64 bits. Keyed, so a pseudonym cannot be
+/// checked against a guessed name without the key.
+fn pseudonym(name: &str, key: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(name.to_lowercase().as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let hash = u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 digests are 32 bytes"));
+    format!("person-{:016x}", hash)
+}
+
+static PSEUDONYM_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Sets the pseudonym key from a key file. Must be called before any name is pseudonymised;
+/// otherwise each run uses a random key.
+fn set_pseudonym_key(key: &[u8]) -> Result<()> {
+    let key = key.trim_ascii();
+    if key.is_empty() {
+        anyhow::bail!("Pseudonym key is empty");
+    }
+    PSEUDONYM_KEY
+        .set(key.to_vec())
+        .map_err(|_| anyhow::anyhow!("Pseudonym key set twice"))
+}
+
+fn pseudonym_key() -> &'static [u8] {
+    PSEUDONYM_KEY.get_or_init(|| {
+        let state = RandomState::new();
+        (0..4u64).flat_map(|block| state.hash_one(block).to_le_bytes()).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_csvs(files: &[(&str, &str)], synonyms: Option<&synonyms::ColumnSynonyms>) -> Vec<DirectoryEntry> {
+        let fingerprints = content::Fingerprints {
+            rows: true,
+            synonyms,
+            ..Default::default()
+        };
+        let files = files
+            .iter()
+            .map(|(name, text)| {
+                let mut file_details = new_file_details(name.to_string(), String::new(), None, None);
+                let csv_meta = extract_csv_metadata_from_reader(text.as_bytes(), usize::MAX, fingerprints).unwrap();
+                file_details.csv_metadata = Some(csv_meta);
+                file_details
+            })
+            .collect();
+        let mut directories = vec![DirectoryEntry {
+            path: "data".to_string(),
+            files,
+        }];
+        if synonyms.is_some() {
+            rehash_column_sets(&mut directories, synonyms, false);
+        }
+        directories
+    }
+
+    fn fuzzy_group(group_id: &str, sources: &[&str]) -> FuzzySimilarityGroup {
+        FuzzySimilarityGroup {
+            group_id: group_id.to_string(),
+            similarity_score: 0.0,
+            min_similarity: 0.0,
+            max_similarity: 0.0,
+            representative_columns: Vec::new(),
+            centroid_columns: Vec::new(),
+            sources: sources.iter().map(|source| source.to_string()).collect(),
+            centroid_similarities: Vec::new(),
+            schema_diff: SchemaDiff {
+                common_columns: Vec::new(),
+                partial_columns: Vec::new(),
+                probable_renames: Vec::new(),
+                order_variants: Vec::new(),
+            },
+            column_correspondences: Vec::new(),
+            member_sets: Vec::new(),
+            scored_pairs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn shared_group_ids_do_not_depend_on_group_order() {
+        let ids = |groups: &[(&str, &[&str])]| {
+            let mut groups: Vec<_> = groups.iter().map(|(id, sources)| fuzzy_group(id, sources)).collect();
+            disambiguate_group_ids(&mut groups);
+            groups.into_iter().map(|group| (group.sources, group.group_id)).collect::<HashMap<_, _>>()
+        };
+        let first: (&str, &[&str]) = ("5d1c8a2e", &["a.csv", "b.csv"]);
+        let second: (&str, &[&str]) = ("5d1c8a2e", &["c.csv", "d.csv"]);
+        let other: (&str, &[&str]) = ("9f04c1b7", &["e.csv", "f.csv"]);
+
+        let forwards = ids(&[first, second, other]);
+        assert_eq!(forwards, ids(&[other, second, first]));
+        assert_eq!(forwards[&vec!["e.csv".to_string(), "f.csv".to_string()]], "9f04c1b7");
+        let first_id = &forwards[&vec!["a.csv".to_string(), "b.csv".to_string()]];
+        let second_id = &forwards[&vec!["c.csv".to_string(), "d.csv".to_string()]];
+        assert!(first_id.starts_with("5d1c8a2e-") && second_id.starts_with("5d1c8a2e-"));
+        assert_ne!(first_id, second_id);
+    }
+
+    fn id_value_rows(ids: std::ops::Range<usize>) -> String {
+        ids.map(|id| format!("{},{}\n", id, id * 7)).collect()
+    }
+
+    /// Families of related schemas: each variant respells, drops and adds columns of its
+    /// family's base schema. Names are random words, so families share few columns.
+    fn schema_variants() -> Vec<Vec<String>> {
+        let mut state: u64 = 1;
+        let mut next = |bound: usize| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) as usize % bound
+        };
+        let mut word = |len: usize| -> String { (0..len).map(|_| (b'a' + next(26) as u8) as char).collect() };
+        let words: Vec<String> = (0..100).map(|_| format!("{}_{}", word(4), word(5))).collect();
+
+        let mut sets = Vec::new();
+        for _ in 0..12 {
+            let mut base: Vec<&str> = Vec::new();
+            while base.len() < 10 {
+                let word = words[next(words.len())].as_str();
+                if !base.contains(&word) {
+                    base.push(word);
+                }
+            }
+            for _ in 0..8 {
+                let mut columns: Vec<String> = base
+                    .iter()
+                    .map(|word| match next(3) {
+                        0 => word.to_string(),
+                        1 => word.replace('_', ""),
+                        _ => word.replace('_', " "),
+                    })
+                    .collect();
+                columns.remove(next(columns.len()));
+                columns.push(words[next(words.len())].clone());
+                sets.push(columns);
+            }
+        }
+        sets
+    }
+
+    #[test]
+    fn lsh_candidates_cover_exhaustive_matches() {
+        let sets = schema_variants();
+        let mut index = minhash::LshIndex::default();
+        for columns in &sets {
+            let tokens = minhash::column_tokens(columns);
+            index.insert(&minhash::signature(tokens.iter().map(|token| token.as_str())));
+        }
+
+        let (mut matches, mut found, mut compared) = (0, 0, 0);
+        for i in 0..sets.len() {
+            let candidates = index.candidates(i);
+            compared += candidates.iter().filter(|&&j| j > i).count();
+            for j in i + 1..sets.len() {
+                if pair_similarity(&sets[i], &sets[j]) >= 0.5 {
+                    matches += 1;
+                    found += usize::from(candidates.binary_search(&j).is_ok());
+                }
+            }
+        }
+        // Pairs scoring well below the default threshold are still candidates, while most
+        // of the unrelated pairs are never compared
+        assert!(matches > 200, "{} matching pairs", matches);
+        assert_eq!(found, matches);
+        assert!(compared * 3 < sets.len() * (sets.len() - 1) / 2, "{} pairs compared", compared);
+    }
+
+    #[test]
+    fn pseudonyms_depend_on_the_key() {
+        let key = b"first key";
+        assert_eq!(pseudonym("Jane Smith", key), pseudonym("jane smith", key));
+        assert_eq!(pseudonym("Jane Smith", key).len(), "person-".len() + 16);
+        assert_ne!(pseudonym("Jane Smith", key), pseudonym("John Smith", key));
+        assert_ne!(pseudonym("Jane Smith", key), pseudonym("Jane Smith", b"second key"));
+    }
+
+    #[test]
+    fn pseudonyms_are_truncated_hmac_sha256() {
+        // RFC 4231 test case 2: HMAC-SHA256("Jefe", "what do ya want for nothing?")
+        assert_eq!(pseudonym("what do ya want for nothing?", b"Jefe"), "person-5bdcc146bf60754e");
+    }
+
+    #[test]
+    fn row_overlap_identical() {
+        let text = format!("id,value\n{}", id_value_rows(0..50));
+        let table = build_row_overlap_table(&scan_csvs(&[("a.csv", &text), ("b.csv", &text)], None));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table[0].relationship, "identical");
+        assert_eq!(table[0].shared_rows, 50);
+        assert!(!table[0].estimated);
+    }
+
+    #[test]
+    fn row_overlap_subset() {
+        // Column order does not matter to row hashes
+        let all = format!("id,value\n{}", id_value_rows(0..50));
+        let some: String = (10..30).map(|id| format!("{},{}\n", id * 7, id)).collect();
+        let some = format!("value,id\n{}", some);
+        let table = build_row_overlap_table(&scan_csvs(&[("a.csv", &all), ("b.csv", &some)], None));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table[0].relationship, "superset");
+        assert_eq!(table[0].shared_rows, 20);
+        assert_eq!(table[0].other_shared_percent, 100.0);
+    }
+
+    #[test]
+    fn row_overlap_disjoint() {
+        let a = format!("id,value\n{}", id_value_rows(0..50));
+        let b = format!("id,value\n{}", id_value_rows(100..150));
+        let table = build_row_overlap_table(&scan_csvs(&[("a.csv", &a), ("b.csv", &b)], None));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn row_overlap_with_column_synonyms() {
+        let synonyms = synonyms::ColumnSynonyms::parse("dob -> date of birth", Path::new("synonyms.txt")).unwrap();
+        let body = "1990-01-01,A1\n1985-06-30,B2\n2001-12-12,C3\n";
+        let a = format!("DOB,NHS\n{}", body);
+        let b = format!("Date of Birth,NHS\n{}", body);
+        let directories = scan_csvs(&[("a.csv", &a), ("b.csv", &b)], Some(&synonyms));
+
+        let column_sets = collect_column_sets(&directories);
+        assert_eq!(column_sets[0].hash, column_sets[1].hash);
+        let table = build_row_overlap_table(&directories);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table[0].relationship, "identical");
+        assert_eq!(table[0].shared_rows, 3);
+    }
+
+    #[test]
+    fn db_files_that_are_not_databases_are_listed_without_a_type() {
+        let options = ScanOptions {
+            enable_hash: false,
+            max_rows: usize::MAX,
+            fingerprints: content::Fingerprints::default(),
+            person_names: PersonNameMode::Keep,
+            scan_archives: false,
+            archive_max_depth: 3,
+            archive_max_total_size: 0,
+        };
+        let directory = std::env::temp_dir().join(format!("db_files_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        // A Thumbs.db cache is an OLE compound file
+        let thumbs = directory.join("Thumbs.db");
+        fs::write(&thumbs, [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1, 0, 0, 0, 0]).unwrap();
+        let sqlite = directory.join("empty.sqlite");
+        fs::write(&sqlite, b"not a database").unwrap();
+
+        let thumbs_details = process_file(&thumbs, &options);
+        let sqlite_details = process_file(&sqlite, &options);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let thumbs_details = thumbs_details.unwrap();
+        assert_eq!(thumbs_details.name, "Thumbs.db");
+        assert_eq!(thumbs_details.file_type, None);
+        assert!(thumbs_details.database_metadata.is_none());
+        assert_eq!(sqlite_details.unwrap().file_type.as_deref(), Some("sqlite"));
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+// 64 bands of 2 rows: sets whose token Jaccard similarity is 0.3 become candidates with
+// probability ~0.997, while sets sharing under 5% of their tokens mostly do not
+const SIGNATURE_LEN: usize = 128;
+const BAND_ROWS: usize = 2;
+
+/// A MinHash signature: for each of `SIGNATURE_LEN` hash functions, the minimum hash
+/// over the set's tokens. The share of equal positions estimates Jaccard similarity.
+pub(crate) type Signature = Vec<u64>;
+
+/// Computes the MinHash signature of a set of tokens. The same tokens always give
+/// the same signature, so signatures can be compared across runs.
+pub(crate) fn signature<'a, I: IntoIterator<Item = &'a str>>(tokens: I) -> Signature {
+    let mut signature = vec![u64::MAX; SIGNATURE_LEN];
+    for token in tokens {
+        add_token_hash(&mut signature, token_hash(token));
+    }
+    signature
+}
+
+/// Hashes a token for `add_token_hash`. Callers that must not reveal which tokens they
+/// saw mix a secret salt into this hash before adding it.
+pub(crate) fn token_hash(token: &str) -> u64 {
+    fnv1a(token.as_bytes())
+}
+
+/// Adds one token to a signature of any length, built up from `u64::MAX` in every slot.
+pub(crate) fn add_token_hash(signature: &mut [u64], base: u64) {
+    for (seed, slot) in signature.iter_mut().enumerate() {
+        let hash = splitmix64(base ^ splitmix64(seed as u64));
+        if hash < *slot {
+            *slot = hash;
+        }
+    }
+}
+
+/// Combines two hashes into one; `mix(a, b)` and `mix(b, a)` differ.
+pub(crate) fn mix(a: u64, b: u64) -> u64 {
+    splitmix64(a ^ splitmix64(b))
+}
+
+/// Estimates the Jaccard similarity of two token sets from their signatures.
+pub(crate) fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    let equal = a.iter().zip(b).filter(|(x, y)| x == y).count();
+    equal as f64 / a.len().max(1) as f64
+}
+
+/// Reduces column names to the first three characters of their lowercase alphanumeric
+/// form. Names that Jaro-Winkler scores as similar nearly always share this prefix
+/// (`patient_id`, `PatientID`, `pat_id`), so token overlap tracks fuzzy set similarity.
+pub(crate) fn column_tokens(columns: &[String]) -> Vec<String> {
+    let mut tokens: Vec<String> = columns
+        .iter()
+        .map(|column| column.to_lowercase().chars().filter(|c| c.is_alphanumeric()).take(3).collect())
+        .collect();
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+/// Buckets signatures by band so that likely-similar items can be found without
+/// comparing every pair. Items are identified by their insertion index.
+#[derive(Default)]
+pub(crate) struct LshIndex {
+    buckets: HashMap<(usize, u64), Vec<usize>>,
+    bands: Vec<Vec<u64>>,
+}
+
+impl LshIndex {
+    pub(crate) fn insert(&mut self, signature: &Signature) {
+        let item = self.bands.len();
+        let bands: Vec<u64> = signature
+            .chunks(BAND_ROWS)
+            .map(|rows| rows.iter().fold(0u64, |hash, &row| splitmix64(hash ^ row)))
+            .collect();
+        for (band, &hash) in bands.iter().enumerate() {
+            self.buckets.entry((band, hash)).or_default().push(item);
+        }
+        self.bands.push(bands);
+    }
+
+    /// Returns the items sharing at least one band with `item`, in ascending order.
+    pub(crate) fn candidates(&self, item: usize) -> Vec<usize> {
+        let mut candidates: Vec<usize> = self.bands[item]
+            .iter()
+            .enumerate()
+            .filter_map(|(band, hash)| self.buckets.get(&(band, *hash)))
+            .flatten()
+            .copied()
+            .filter(|&other| other != item)
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
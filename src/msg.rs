@@ -0,0 +1,354 @@
+use anyhow::Result;
+use cfb::CompoundFile;
+use chrono::DateTime;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::archive::ArchiveBudget;
+use crate::email::{address_domains, attachment_from_bytes, MAX_ATTACHMENT_DEPTH};
+use crate::{new_file_details, redact_nhs_numbers, EmailAttachment, EmlMetadata, ScanOptions};
+
+// MAPI property tags read from the message, recipient and attachment storages
+const PR_SUBJECT: u16 = 0x0037;
+const PR_CLIENT_SUBMIT_TIME: u16 = 0x0039;
+const PR_SENT_REPRESENTING_EMAIL_ADDRESS: u16 = 0x0065;
+const PR_SENDER_EMAIL_ADDRESS: u16 = 0x0C1F;
+const PR_RECIPIENT_TYPE: u16 = 0x0C15;
+const PR_NORMALIZED_SUBJECT: u16 = 0x0E1D;
+const PR_MESSAGE_DELIVERY_TIME: u16 = 0x0E06;
+const PR_DISPLAY_NAME: u16 = 0x3001;
+const PR_EMAIL_ADDRESS: u16 = 0x3003;
+const PR_ATTACH_DATA: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+const PR_SMTP_ADDRESS: u16 = 0x39FE;
+const PR_SENDER_SMTP_ADDRESS: u16 = 0x5D01;
+
+// MAPI property types
+const PT_LONG: u16 = 0x0003;
+const PT_SYSTIME: u16 = 0x0040;
+const PT_STRING8: u16 = 0x001E;
+const PT_UNICODE: u16 = 0x001F;
+const PT_OBJECT: u16 = 0x000D;
+const PT_BINARY: u16 = 0x0102;
+
+// The fixed-length property stream starts with a header whose size depends on the storage
+const TOP_LEVEL_HEADER_LEN: usize = 32;
+const EMBEDDED_MESSAGE_HEADER_LEN: usize = 24;
+const SUB_OBJECT_HEADER_LEN: usize = 8;
+
+const PROPERTIES_STREAM: &str = "__properties_version1.0";
+const RECIPIENT_PREFIX: &str = "__recip_version1.0_";
+const ATTACHMENT_PREFIX: &str = "__attach_version1.0_";
+
+// Seconds between the FILETIME epoch (1601) and the Unix epoch
+const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+/// Reads the headers and attachments of an Outlook message stored as an OLE2 compound file.
+pub(crate) fn extract_msg_metadata<F: Read + Seek>(
+    compound: &mut CompoundFile<F>,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> Result<EmlMetadata> {
+    let root = PathBuf::from("/");
+    if !compound.is_stream(root.join(PROPERTIES_STREAM)) {
+        anyhow::bail!("Not an Outlook message");
+    }
+    Ok(message_metadata(compound, &root, TOP_LEVEL_HEADER_LEN, options, depth, budget))
+}
+
+fn message_metadata<F: Read + Seek>(
+    compound: &mut CompoundFile<F>,
+    storage: &Path,
+    header_len: usize,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> EmlMetadata {
+    let properties = read_stream(compound, &storage.join(PROPERTIES_STREAM)).unwrap_or_default();
+
+    let date = [PR_CLIENT_SUBMIT_TIME, PR_MESSAGE_DELIVERY_TIME]
+        .into_iter()
+        .find_map(|tag| fixed_property(&properties, header_len, tag, PT_SYSTIME))
+        .and_then(filetime_to_string);
+
+    let subject = [PR_SUBJECT, PR_NORMALIZED_SUBJECT]
+        .into_iter()
+        .find_map(|tag| string_property(compound, storage, tag))
+        .map(|subject| redact_nhs_numbers(subject.trim()));
+
+    // Exchange senders and recipients may carry a distinguished name before the SMTP address,
+    // so the first value with a domain is used
+    let from_address = [PR_SENDER_SMTP_ADDRESS, PR_SENDER_EMAIL_ADDRESS, PR_SENT_REPRESENTING_EMAIL_ADDRESS]
+        .into_iter()
+        .filter_map(|tag| string_property(compound, storage, tag))
+        .find(|address| address.contains('@'));
+    let from = address_domains(from_address.as_deref());
+
+    let mut to_addresses = Vec::new();
+    let mut cc_addresses = Vec::new();
+    for recipient in child_storages(compound, storage, RECIPIENT_PREFIX) {
+        let Some(address) = [PR_SMTP_ADDRESS, PR_EMAIL_ADDRESS]
+            .into_iter()
+            .filter_map(|tag| string_property(compound, &recipient, tag))
+            .find(|address| address.contains('@'))
+        else {
+            continue;
+        };
+        let recipient_properties =
+            read_stream(compound, &recipient.join(PROPERTIES_STREAM)).unwrap_or_default();
+        // 1 = To, 2 = Cc, 3 = Bcc; Bcc is only present on the sender's copy and is not reported
+        match fixed_property(&recipient_properties, SUB_OBJECT_HEADER_LEN, PR_RECIPIENT_TYPE, PT_LONG) {
+            Some(2) => cc_addresses.push(address),
+            Some(3) => {}
+            _ => to_addresses.push(address),
+        }
+    }
+    let to = address_domains(to_addresses.iter().map(|address| address.as_str()));
+    let cc = address_domains(cc_addresses.iter().map(|address| address.as_str()));
+
+    // Attachments have no filesystem time of their own, so they take the sent date
+    let attachment_created = date.clone().unwrap_or_default();
+
+    let attachments = child_storages(compound, storage, ATTACHMENT_PREFIX)
+        .into_iter()
+        .filter_map(|attachment| {
+            attachment_metadata(compound, &attachment, &attachment_created, options, depth, budget)
+        })
+        .collect();
+
+    EmlMetadata {
+        date,
+        subject,
+        from,
+        to,
+        cc,
+        mime_structure: None,
+        attachments,
+    }
+}
+
+fn attachment_metadata<F: Read + Seek>(
+    compound: &mut CompoundFile<F>,
+    storage: &Path,
+    created: &str,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> Option<EmailAttachment> {
+    let mut name = [PR_ATTACH_LONG_FILENAME, PR_ATTACH_FILENAME, PR_DISPLAY_NAME]
+        .into_iter()
+        .find_map(|tag| string_property(compound, storage, tag))
+        .unwrap_or_else(|| "attachment".to_string());
+
+    // Attached Outlook items are stored as a nested message storage rather than a byte stream
+    let embedded = storage.join(property_name(PR_ATTACH_DATA, PT_OBJECT));
+    if compound.is_storage(&embedded) {
+        if !name.to_lowercase().ends_with(".msg") {
+            name.push_str(".msg");
+        }
+        let size = compound
+            .walk_storage(&embedded)
+            .map(|entries| entries.filter(|entry| entry.is_stream()).map(|entry| entry.len()).sum::<u64>())
+            .unwrap_or(0);
+
+        let metadata = (depth < MAX_ATTACHMENT_DEPTH).then(|| {
+            let mut details = new_file_details(redact_nhs_numbers(&name), created.to_string(), Some(size), None);
+            details.file_type = Some("msg".to_string());
+            details.msg_metadata = Some(message_metadata(
+                compound,
+                &embedded,
+                EMBEDDED_MESSAGE_HEADER_LEN,
+                options,
+                depth + 1,
+                budget,
+            ));
+            details
+        });
+
+        return Some(EmailAttachment {
+            name: redact_nhs_numbers(&name),
+            size: size as usize,
+            content_type: "application/vnd.ms-outlook".to_string(),
+            metadata,
+        });
+    }
+
+    // Attachments without data (such as links to files) are skipped
+    let contents = read_stream(compound, &storage.join(property_name(PR_ATTACH_DATA, PT_BINARY)))?;
+    let content_type = string_property(compound, storage, PR_ATTACH_MIME_TAG)
+        .map(|mime| mime.trim().to_lowercase())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    Some(attachment_from_bytes(&name, content_type, &contents, created, options, depth, budget))
+}
+
+/// Lists recipient or attachment storages directly below `storage`, in name order.
+fn child_storages<F>(compound: &CompoundFile<F>, storage: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut children: Vec<PathBuf> = compound
+        .read_storage(storage)
+        .map(|entries| {
+            entries
+                .filter(|entry| entry.is_storage() && entry.name().starts_with(prefix))
+                .map(|entry| entry.path().to_path_buf())
+                .collect()
+        })
+        .unwrap_or_default();
+    children.sort();
+    children
+}
+
+fn property_name(tag: u16, property_type: u16) -> String {
+    format!("__substg1.0_{:04X}{:04X}", tag, property_type)
+}
+
+fn read_stream<F: Read + Seek>(compound: &mut CompoundFile<F>, path: &Path) -> Option<Vec<u8>> {
+    let mut stream = compound.open_stream(path).ok()?;
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// Reads a variable-length string property, stored as UTF-16LE or in the 8-bit code page.
+fn string_property<F: Read + Seek>(compound: &mut CompoundFile<F>, storage: &Path, tag: u16) -> Option<String> {
+    let value = if let Some(bytes) = read_stream(compound, &storage.join(property_name(tag, PT_UNICODE))) {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        let bytes = read_stream(compound, &storage.join(property_name(tag, PT_STRING8)))?;
+        String::from_utf8_lossy(&bytes).to_string()
+    };
+
+    let value = value.trim_end_matches('\0').to_string();
+    if value.trim().is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Looks up a fixed-length property in a properties stream. Each entry is 16 bytes:
+/// the property tag, flags and an 8-byte value.
+fn fixed_property(properties: &[u8], header_len: usize, tag: u16, property_type: u16) -> Option<u64> {
+    let wanted = ((tag as u32) << 16) | property_type as u32;
+    properties
+        .get(header_len..)?
+        .chunks_exact(16)
+        .find(|entry| u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) == wanted)
+        .map(|entry| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(&entry[8..16]);
+            u64::from_le_bytes(value)
+        })
+}
+
+fn filetime_to_string(filetime: u64) -> Option<String> {
+    let seconds = (filetime / 10_000_000) as i64 - FILETIME_UNIX_OFFSET;
+    DateTime::from_timestamp(seconds, 0).map(|date| date.format("%Y-%m-%dT%H:%M").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{content, PersonNameMode};
+    use std::io::{Cursor, Write};
+
+    // 2024-03-01T09:30 UTC
+    const SENT: u64 = (1_709_285_400 + FILETIME_UNIX_OFFSET as u64) * 10_000_000;
+    const EXTRACT: &[u8] = b"id,value\n1,2\n3,4\n";
+
+    fn options() -> ScanOptions<'static> {
+        ScanOptions {
+            enable_hash: false,
+            max_rows: usize::MAX,
+            fingerprints: content::Fingerprints::default(),
+            person_names: PersonNameMode::Keep,
+            scan_archives: false,
+            archive_max_depth: 3,
+            archive_max_total_size: 1 << 20,
+        }
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect()
+    }
+
+    fn properties(header_len: usize, tag: u16, property_type: u16, value: u64) -> Vec<u8> {
+        let mut bytes = vec![0; header_len];
+        bytes.extend((((tag as u32) << 16) | property_type as u32).to_le_bytes());
+        bytes.extend([0; 4]);
+        bytes.extend(value.to_le_bytes());
+        bytes
+    }
+
+    fn write_stream(compound: &mut CompoundFile<Cursor<Vec<u8>>>, storage: &str, name: &str, bytes: &[u8]) {
+        let path = format!("{storage}/{name}");
+        compound.create_stream(path).unwrap().write_all(bytes).unwrap();
+    }
+
+    fn add_recipient(compound: &mut CompoundFile<Cursor<Vec<u8>>>, index: usize, address: &str, recipient_type: u64) {
+        let storage = format!("/{RECIPIENT_PREFIX}#{index:08X}");
+        compound.create_storage(&storage).unwrap();
+        let type_properties = properties(SUB_OBJECT_HEADER_LEN, PR_RECIPIENT_TYPE, PT_LONG, recipient_type);
+        write_stream(compound, &storage, PROPERTIES_STREAM, &type_properties);
+        write_stream(compound, &storage, &property_name(PR_DISPLAY_NAME, PT_UNICODE), &utf16("Someone"));
+        // Exchange recipients hold a distinguished name in the address property
+        let tag = if address.contains('@') { PR_SMTP_ADDRESS } else { PR_EMAIL_ADDRESS };
+        write_stream(compound, &storage, &property_name(tag, PT_UNICODE), &utf16(address));
+    }
+
+    fn add_attachment(compound: &mut CompoundFile<Cursor<Vec<u8>>>, index: usize, name: &str, mime: &str, data: &[u8]) {
+        let storage = format!("/{ATTACHMENT_PREFIX}#{index:08X}");
+        compound.create_storage(&storage).unwrap();
+        write_stream(compound, &storage, &property_name(PR_ATTACH_LONG_FILENAME, PT_UNICODE), &utf16(name));
+        write_stream(compound, &storage, &property_name(PR_ATTACH_MIME_TAG, PT_STRING8), mime.as_bytes());
+        write_stream(compound, &storage, &property_name(PR_ATTACH_DATA, PT_BINARY), data);
+    }
+
+    #[test]
+    fn reads_subject_addresses_and_attachments() {
+        let mut compound = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        let sent = properties(TOP_LEVEL_HEADER_LEN, PR_CLIENT_SUBMIT_TIME, PT_SYSTIME, SENT);
+        write_stream(&mut compound, "", PROPERTIES_STREAM, &sent);
+        write_stream(&mut compound, "", &property_name(PR_SUBJECT, PT_UNICODE), &utf16("Monthly extract 943 476 5919\0"));
+        write_stream(&mut compound, "", &property_name(PR_SENDER_EMAIL_ADDRESS, PT_UNICODE), &utf16("/O=TRUST/CN=JSMITH"));
+        write_stream(&mut compound, "", &property_name(PR_SENDER_SMTP_ADDRESS, PT_STRING8), b"Jane.Smith@NHS.net");
+
+        add_recipient(&mut compound, 0, "a.patel@nhs.net", 1);
+        add_recipient(&mut compound, 1, "ops@Example.org", 1);
+        add_recipient(&mut compound, 2, "analyst@example.org", 2);
+        add_recipient(&mut compound, 3, "/O=TRUST/OU=EXCHANGE/CN=RECIPIENTS/CN=BOB", 2);
+        add_recipient(&mut compound, 4, "hidden@elsewhere.org", 3);
+        add_attachment(&mut compound, 0, "extract.csv", "text/csv", EXTRACT);
+        add_attachment(&mut compound, 1, "notes.bin", "Application/Octet-Stream", &[0; 5]);
+
+        let mut budget = ArchiveBudget::new(1 << 20);
+        let metadata = extract_msg_metadata(&mut compound, &options(), 0, &mut budget).unwrap();
+
+        assert_eq!(metadata.date.as_deref(), Some("2024-03-01T09:30"));
+        assert_eq!(metadata.subject.as_deref(), Some("Monthly extract [REDACTED]"));
+        assert_eq!(metadata.from, ["nhs.net"]);
+        assert_eq!(metadata.to, ["example.org", "nhs.net"]);
+        assert_eq!(metadata.cc, ["example.org"]);
+        assert!(metadata.mime_structure.is_none());
+
+        let attachments: Vec<(&str, usize, &str)> = metadata
+            .attachments
+            .iter()
+            .map(|attachment| (attachment.name.as_str(), attachment.size, attachment.content_type.as_str()))
+            .collect();
+        assert_eq!(
+            attachments,
+            [("extract.csv", EXTRACT.len(), "text/csv"), ("notes.bin", 5, "application/octet-stream")]
+        );
+        let csv = metadata.attachments[0].metadata.as_ref().unwrap();
+        assert_eq!(csv.file_type.as_deref(), Some("csv"));
+        assert_eq!(csv.created, "2024-03-01T09:30");
+        assert!(metadata.attachments[1].metadata.is_none());
+    }
+}
@@ -0,0 +1,332 @@
+use cfb::CompoundFile;
+use chrono::DateTime;
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use crate::{redact_nhs_numbers, redact_person_name, DocumentProperties, OleMetadata, PersonNameMode};
+
+const SUMMARY_INFORMATION: &str = "/\u{5}SummaryInformation";
+const DOCUMENT_SUMMARY_INFORMATION: &str = "/\u{5}DocumentSummaryInformation";
+
+// Property IDs in the SummaryInformation set
+const PID_CODEPAGE: u32 = 0x01;
+const PID_TITLE: u32 = 0x02;
+const PID_AUTHOR: u32 = 0x04;
+const PID_LASTAUTHOR: u32 = 0x08;
+const PID_CREATE_DTM: u32 = 0x0C;
+const PID_LASTSAVE_DTM: u32 = 0x0D;
+const PID_PAGECOUNT: u32 = 0x0E;
+const PID_WORDCOUNT: u32 = 0x0F;
+const PID_APPNAME: u32 = 0x12;
+
+// Property IDs in the DocumentSummaryInformation set
+const PID_SLIDECOUNT: u32 = 0x07;
+const PID_COMPANY: u32 = 0x0F;
+
+// Property value types
+const VT_I2: u32 = 0x02;
+const VT_I4: u32 = 0x03;
+const VT_LPSTR: u32 = 0x1E;
+const VT_LPWSTR: u32 = 0x1F;
+const VT_FILETIME: u32 = 0x40;
+
+const CODEPAGE_UTF16: i64 = 1200;
+const CODEPAGE_UTF8: i64 = 65001;
+
+// Seconds between the FILETIME epoch (1601) and the Unix epoch
+const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+/// A property value as stored in a property set stream.
+#[derive(Debug, PartialEq)]
+enum PropertyValue {
+    Integer(i64),
+    Text(String),
+    FileTime(u64),
+}
+
+/// Reads the SummaryInformation and DocumentSummaryInformation property sets that legacy
+/// Office files (.doc, .xls, .ppt) keep alongside their content. Returns `None` when
+/// neither stream is present.
+pub(crate) fn extract_ole_metadata<F: Read + Seek>(
+    compound: &mut CompoundFile<F>,
+    person_names: PersonNameMode,
+) -> Option<OleMetadata> {
+    let summary = read_property_set(compound, SUMMARY_INFORMATION);
+    let document_summary = read_property_set(compound, DOCUMENT_SUMMARY_INFORMATION);
+    if summary.is_none() && document_summary.is_none() {
+        return None;
+    }
+
+    let summary = summary.unwrap_or_default();
+    let document_summary = document_summary.unwrap_or_default();
+
+    let text = |properties: &HashMap<u32, PropertyValue>, id: u32| match properties.get(&id) {
+        Some(PropertyValue::Text(text)) if !text.trim().is_empty() => Some(text.trim().to_string()),
+        _ => None,
+    };
+    let integer = |properties: &HashMap<u32, PropertyValue>, id: u32| match properties.get(&id) {
+        Some(PropertyValue::Integer(value)) if *value >= 0 => Some(*value as usize),
+        _ => None,
+    };
+    let date = |id: u32| match summary.get(&id) {
+        Some(PropertyValue::FileTime(filetime)) => filetime_to_string(*filetime),
+        _ => None,
+    };
+
+    let properties = DocumentProperties {
+        title: text(&summary, PID_TITLE).map(|title| redact_nhs_numbers(&title)),
+        creator: text(&summary, PID_AUTHOR).map(|author| redact_person_name(&author, person_names)),
+        last_modified_by: text(&summary, PID_LASTAUTHOR).map(|author| redact_person_name(&author, person_names)),
+        created: date(PID_CREATE_DTM),
+        modified: date(PID_LASTSAVE_DTM),
+        company: text(&document_summary, PID_COMPANY).map(|company| redact_nhs_numbers(&company)),
+        application: text(&summary, PID_APPNAME).map(|application| redact_nhs_numbers(&application)),
+        app_version: None,
+    };
+
+    Some(OleMetadata {
+        properties,
+        page_count: integer(&summary, PID_PAGECOUNT),
+        word_count: integer(&summary, PID_WORDCOUNT),
+        slide_count: integer(&document_summary, PID_SLIDECOUNT),
+    })
+}
+
+/// Parses the first section of a property set stream into a map of property ID to value.
+fn read_property_set<F: Read + Seek>(
+    compound: &mut CompoundFile<F>,
+    stream_name: &str,
+) -> Option<HashMap<u32, PropertyValue>> {
+    let mut stream = compound.open_stream(stream_name).ok()?;
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).ok()?;
+    parse_property_set(&bytes)
+}
+
+/// Parses a property set stream. Returns `None` when the header or section is cut short;
+/// properties whose offsets or lengths run past the end of the stream are left out.
+fn parse_property_set(bytes: &[u8]) -> Option<HashMap<u32, PropertyValue>> {
+    let u16_at = |offset: usize| bytes.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    // Header: byte order, version, system identifier, CLSID and section count, then
+    // the first section's FMTID and offset
+    if u16_at(0)? != 0xFFFE || u32_at(24)? == 0 {
+        return None;
+    }
+    let section = u32_at(44)? as usize;
+    let property_count = u32_at(section + 4)? as usize;
+
+    let mut entries = Vec::new();
+    for index in 0..property_count {
+        let entry = section + 8 + index * 8;
+        let (Some(id), Some(offset)) = (u32_at(entry), u32_at(entry + 4)) else {
+            break;
+        };
+        entries.push((id, section + offset as usize));
+    }
+
+    // Strings are stored in the code page given by property 1
+    let codepage = entries
+        .iter()
+        .find(|(id, _)| *id == PID_CODEPAGE)
+        .and_then(|(_, offset)| u16_at(offset + 4))
+        .map(|codepage| codepage as i64);
+
+    let mut properties = HashMap::new();
+    for (id, offset) in entries {
+        let Some(value_type) = u32_at(offset) else {
+            continue;
+        };
+        let value = match value_type {
+            VT_I2 => u16_at(offset + 4).map(|value| PropertyValue::Integer(value as i16 as i64)),
+            VT_I4 => u32_at(offset + 4).map(|value| PropertyValue::Integer(value as i32 as i64)),
+            VT_FILETIME => bytes.get(offset + 4..offset + 12).map(|b| {
+                let mut array = [0u8; 8];
+                array.copy_from_slice(b);
+                PropertyValue::FileTime(u64::from_le_bytes(array))
+            }),
+            VT_LPSTR => u32_at(offset + 4).and_then(|len| {
+                let text = bytes.get(offset + 8..offset + 8 + len as usize)?;
+                Some(PropertyValue::Text(decode_string(text, codepage)))
+            }),
+            VT_LPWSTR => u32_at(offset + 4).and_then(|len| {
+                let text = bytes.get(offset + 8..offset + 8 + 2 * len as usize)?;
+                Some(PropertyValue::Text(decode_string(text, Some(CODEPAGE_UTF16))))
+            }),
+            _ => None,
+        };
+        if let Some(value) = value {
+            properties.insert(id, value);
+        }
+    }
+
+    Some(properties)
+}
+
+/// Decodes a property string, treating single-byte code pages other than UTF-8 as Latin-1.
+fn decode_string(bytes: &[u8], codepage: Option<i64>) -> String {
+    let text = match codepage {
+        Some(CODEPAGE_UTF16) => {
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        Some(CODEPAGE_UTF8) => String::from_utf8_lossy(bytes).to_string(),
+        _ => bytes.iter().map(|&b| b as char).collect(),
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+fn filetime_to_string(filetime: u64) -> Option<String> {
+    if filetime == 0 {
+        return None;
+    }
+    let seconds = (filetime / 10_000_000) as i64 - FILETIME_UNIX_OFFSET;
+    DateTime::from_timestamp(seconds, 0).map(|date| date.format("%Y-%m-%dT%H:%M").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    // The en dash only decodes correctly through the UTF-8 code page
+    const TITLE: &str = "Quarterly report – Q1";
+    // 2024-03-01T09:30 UTC
+    const CREATED: u64 = (1_709_285_400 + FILETIME_UNIX_OFFSET as u64) * 10_000_000;
+
+    fn typed(value_type: u32, value: &[u8]) -> Vec<u8> {
+        let mut bytes = value_type.to_le_bytes().to_vec();
+        bytes.extend(value);
+        bytes
+    }
+
+    fn lpstr(text: &str) -> Vec<u8> {
+        let mut value = (text.len() as u32 + 1).to_le_bytes().to_vec();
+        value.extend(text.as_bytes());
+        value.push(0);
+        typed(VT_LPSTR, &value)
+    }
+
+    fn lpwstr(text: &str) -> Vec<u8> {
+        let units: Vec<u16> = text.encode_utf16().chain([0]).collect();
+        let mut value = (units.len() as u32).to_le_bytes().to_vec();
+        value.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+        typed(VT_LPWSTR, &value)
+    }
+
+    /// A property set stream with one section holding the given properties, in order.
+    fn property_set(properties: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = vec![0xFE, 0xFF, 0, 0, 0, 0, 0, 0];
+        bytes.extend([0; 16]);
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend([0; 16]);
+        bytes.extend(48u32.to_le_bytes());
+
+        // Section: size, property count, then (ID, offset) entries and the values
+        let mut offset = 8 + 8 * properties.len();
+        let mut values: Vec<u8> = Vec::new();
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend((properties.len() as u32).to_le_bytes());
+        for (id, value) in properties {
+            bytes.extend(id.to_le_bytes());
+            bytes.extend((offset as u32).to_le_bytes());
+            values.extend(value);
+            offset += value.len();
+        }
+        bytes.extend(values);
+        bytes
+    }
+
+    /// The code page, then a UTF-16 author, page count, created date and an 8-bit title.
+    fn summary_stream() -> Vec<u8> {
+        property_set(&[
+            (PID_CODEPAGE, typed(VT_I2, &(CODEPAGE_UTF8 as u32).to_le_bytes())),
+            (PID_AUTHOR, lpwstr("Jane Smith")),
+            (PID_PAGECOUNT, typed(VT_I4, &12u32.to_le_bytes())),
+            (PID_CREATE_DTM, typed(VT_FILETIME, &CREATED.to_le_bytes())),
+            (PID_TITLE, lpstr(TITLE)),
+        ])
+    }
+
+    // Where the title's entry and value sit in `summary_stream`
+    const TITLE_OFFSET_AT: usize = 48 + 8 + 4 * 8 + 4;
+    const TITLE_LENGTH_AT: usize = 48 + 8 + 5 * 8 + 8 + 30 + 8 + 12 + 4;
+
+    fn text(properties: &HashMap<u32, PropertyValue>, id: u32) -> Option<&str> {
+        match properties.get(&id) {
+            Some(PropertyValue::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn reads_each_value_type() {
+        let properties = parse_property_set(&summary_stream()).unwrap();
+        assert_eq!(text(&properties, PID_AUTHOR), Some("Jane Smith"));
+        assert_eq!(properties[&PID_PAGECOUNT], PropertyValue::Integer(12));
+        assert_eq!(properties[&PID_CREATE_DTM], PropertyValue::FileTime(CREATED));
+        assert_eq!(text(&properties, PID_TITLE), Some(TITLE));
+    }
+
+    #[test]
+    fn extracts_document_properties() {
+        let mut compound = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        compound.create_stream(SUMMARY_INFORMATION).unwrap().write_all(&summary_stream()).unwrap();
+
+        let metadata = extract_ole_metadata(&mut compound, PersonNameMode::Redact).unwrap();
+        assert_eq!(metadata.properties.title.as_deref(), Some(TITLE));
+        assert_eq!(metadata.properties.creator.as_deref(), Some("[REDACTED]"));
+        assert_eq!(metadata.properties.created.as_deref(), Some("2024-03-01T09:30"));
+        assert_eq!(metadata.page_count, Some(12));
+        assert_eq!(metadata.slide_count, None);
+    }
+
+    #[test]
+    fn truncation_keeps_the_properties_before_the_cut() {
+        let stream = summary_stream();
+        assert_eq!(TITLE_LENGTH_AT + 4 + TITLE.len() + 1, stream.len());
+
+        // Without the byte order mark, section count or section header there is no property set
+        for len in [0, 1, 27, 47, 55] {
+            assert!(parse_property_set(&stream[..len]).is_none(), "truncated to {len} bytes");
+        }
+
+        // Cut inside the title: everything stored before it is still read
+        let properties = parse_property_set(&stream[..stream.len() - 1]).unwrap();
+        assert_eq!(text(&properties, PID_AUTHOR), Some("Jane Smith"));
+        assert_eq!(properties[&PID_CREATE_DTM], PropertyValue::FileTime(CREATED));
+        assert_eq!(text(&properties, PID_TITLE), None);
+
+        // Cut inside the entry table: only whole entries whose values are present are read
+        let properties = parse_property_set(&stream[..48 + 8 + 2 * 8 + 4]).unwrap();
+        assert!(properties.is_empty());
+    }
+
+    #[test]
+    fn out_of_range_offsets_and_lengths_are_left_out() {
+        let corrupt = |position: usize| {
+            let mut stream = summary_stream();
+            stream[position..position + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            parse_property_set(&stream)
+        };
+
+        assert!(corrupt(44).is_none(), "section offset");
+        for position in [TITLE_OFFSET_AT, TITLE_LENGTH_AT] {
+            let properties = corrupt(position).unwrap();
+            assert_eq!(text(&properties, PID_TITLE), None, "corrupt u32 at {position}");
+            assert_eq!(text(&properties, PID_AUTHOR), Some("Jane Smith"), "corrupt u32 at {position}");
+        }
+
+        // A property count past the end of the stream stops at the last whole entry
+        let properties = corrupt(52).unwrap();
+        assert_eq!(text(&properties, PID_TITLE), Some(TITLE));
+    }
+}
@@ -0,0 +1,572 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::archive::{zip_time, ArchiveBudget};
+use crate::email::MAX_ATTACHMENT_DEPTH;
+use crate::{
+    process_embedded_file, redact_nhs_numbers, redact_person_name, DocumentProperties, DocxMetadata,
+    DocxTableMetadata, ExcelMetadata, FileDetails, PersonNameMode, PptxMetadata, PptxTableMetadata,
+    ScanOptions,
+};
+
+// Workbooks embedded as OLE objects keep their own extension inside `embeddings/`
+const WORKBOOK_EXTENSIONS: [&str; 4] = ["xlsx", "xlsm", "xlsb", "xls"];
+
+/// Reads a single part of an OOXML package into a string, if present.
+pub(crate) fn read_part<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Option<String> {
+    let mut part = archive.by_name(name).ok()?;
+    let mut content = String::new();
+    part.read_to_string(&mut content).ok()?;
+    Some(content)
+}
+
+/// Collects the text of the direct children of the root element, keyed by local name.
+/// This is enough for the flat `docProps/core.xml` and `docProps/app.xml` parts.
+pub(crate) fn read_flat_xml_fields(xml: &str) -> HashMap<String, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut fields = HashMap::new();
+    let mut depth = 0;
+    let mut current: Option<String> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                depth += 1;
+                current = if depth == 2 {
+                    Some(String::from_utf8_lossy(e.local_name().as_ref()).to_string())
+                } else {
+                    None
+                };
+            }
+            Ok(Event::Text(t)) => {
+                if let (Some(name), Ok(text)) = (&current, t.unescape()) {
+                    fields.insert(name.clone(), text.to_string());
+                }
+            }
+            Ok(Event::End(_)) => {
+                depth -= 1;
+                current = None;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    fields
+}
+
+/// Extracts core (`docProps/core.xml`) and extended (`docProps/app.xml`) properties
+/// from an OOXML package. Returns `None` when the package carries neither part.
+pub(crate) fn extract_document_properties<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    person_names: PersonNameMode,
+) -> Option<DocumentProperties> {
+    let core = read_part(archive, "docProps/core.xml").map(|xml| read_flat_xml_fields(&xml));
+    let app = read_part(archive, "docProps/app.xml").map(|xml| read_flat_xml_fields(&xml));
+
+    if core.is_none() && app.is_none() {
+        return None;
+    }
+
+    let core = core.unwrap_or_default();
+    let app = app.unwrap_or_default();
+    let text = |fields: &HashMap<String, String>, key: &str| {
+        fields
+            .get(key)
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| redact_nhs_numbers(v.trim()))
+    };
+    let person = |key: &str| {
+        core.get(key)
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| redact_person_name(v, person_names))
+    };
+
+    Some(DocumentProperties {
+        title: text(&core, "title"),
+        creator: person("creator"),
+        last_modified_by: person("lastModifiedBy"),
+        created: core.get("created").map(|v| normalise_w3c_date(v)),
+        modified: core.get("modified").map(|v| normalise_w3c_date(v)),
+        company: text(&app, "Company"),
+        application: text(&app, "Application"),
+        app_version: text(&app, "AppVersion"),
+    })
+}
+
+/// Opens an OOXML package from disk.
+pub(crate) fn open_package(path: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(path)?;
+    Ok(ZipArchive::new(file)?)
+}
+
+/// Adds the information only available from the OOXML package itself (document
+/// properties and workbook/sheet protection) to metadata extracted by calamine.
+pub(crate) fn annotate_workbook<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    excel_meta: &mut ExcelMetadata,
+    person_names: PersonNameMode,
+) {
+    excel_meta.properties = extract_document_properties(archive, person_names);
+
+    // Protection is only read from XML workbooks; .xlsb stores its parts as BIFF12
+    let Some(workbook_xml) = read_part(archive, "xl/workbook.xml") else {
+        return;
+    };
+
+    let (workbook_protected, sheet_parts) = parse_workbook_part(&workbook_xml);
+    let relationships = read_part(archive, "xl/_rels/workbook.xml.rels")
+        .map(|xml| parse_relationships(&xml))
+        .unwrap_or_default();
+
+    excel_meta.workbook_protected = Some(workbook_protected);
+
+    for (sheet_name, relationship_id) in sheet_parts {
+        let Some(target) = relationships.get(&relationship_id) else {
+            continue;
+        };
+        let part_name = resolve_part_name("xl", target);
+        let protected = sheet_part_is_protected(archive, &part_name);
+
+        let redacted_name = redact_nhs_numbers(&sheet_name);
+        if let Some(sheet) = excel_meta
+            .sheets
+            .iter_mut()
+            .find(|sheet| sheet.sheet_name == redacted_name)
+        {
+            sheet.protected = Some(protected);
+        }
+    }
+}
+
+/// Extracts properties, counts, table headers, revisions and comments from a DOCX package.
+pub(crate) fn extract_docx_metadata<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    person_names: PersonNameMode,
+) -> Result<DocxMetadata> {
+    let app = read_part(archive, "docProps/app.xml")
+        .map(|xml| read_flat_xml_fields(&xml))
+        .unwrap_or_default();
+    let count = |key: &str| app.get(key).and_then(|v| v.trim().parse::<usize>().ok());
+
+    let body = scan_document_body(archive, "word/document.xml")?;
+
+    let comment_count = archive
+        .by_name("word/comments.xml")
+        .ok()
+        .map(|part| count_elements(BufReader::new(part), b"comment"))
+        .unwrap_or(0);
+
+    let part_names: Vec<String> = archive.file_names().map(|name| name.to_string()).collect();
+    let embedded_object_count = part_names
+        .iter()
+        .filter(|name| name.starts_with("word/embeddings/"))
+        .count();
+    let image_count = part_names
+        .iter()
+        .filter(|name| name.starts_with("word/media/"))
+        .count();
+
+    Ok(DocxMetadata {
+        properties: extract_document_properties(archive, person_names),
+        page_count: count("Pages"),
+        word_count: count("Words"),
+        paragraph_count: count("Paragraphs").unwrap_or(body.paragraph_count),
+        table_count: body.tables.len(),
+        tables: body.tables,
+        tracked_change_count: body.tracked_change_count,
+        comment_count,
+        embedded_object_count,
+        image_count,
+    })
+}
+
+/// Extracts properties, slide count, table headers and embedded objects from a PPTX package.
+pub(crate) fn extract_pptx_metadata<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    person_names: PersonNameMode,
+) -> Result<PptxMetadata> {
+    let slide_parts = slide_part_names(archive)?;
+
+    // DrawingML tables use the same tbl/tr/tc/t element names as WordprocessingML
+    let mut tables = Vec::new();
+    for (index, part_name) in slide_parts.iter().enumerate() {
+        if let Ok(body) = scan_document_body(archive, part_name) {
+            tables.extend(body.tables.into_iter().map(|table| PptxTableMetadata {
+                slide: index + 1,
+                header_row: table.header_row,
+                row_count: table.row_count,
+            }));
+        }
+    }
+
+    let part_names: Vec<String> = archive.file_names().map(|name| name.to_string()).collect();
+    let embedded_parts: Vec<&String> = part_names
+        .iter()
+        .filter(|name| name.starts_with("ppt/embeddings/"))
+        .collect();
+    let embedded_workbooks = embedded_parts
+        .iter()
+        .filter(|name| is_embedded_workbook(name))
+        .map(|name| redact_nhs_numbers(name.trim_start_matches("ppt/embeddings/")))
+        .collect();
+    let image_count = part_names
+        .iter()
+        .filter(|name| name.starts_with("ppt/media/"))
+        .count();
+
+    Ok(PptxMetadata {
+        properties: extract_document_properties(archive, person_names),
+        slide_count: slide_parts.len(),
+        table_count: tables.len(),
+        tables,
+        embedded_workbooks,
+        embedded_object_count: embedded_parts.len(),
+        image_count,
+    })
+}
+
+/// Lists slide parts in presentation order, following `sldIdLst` in `presentation.xml`
+/// through its relationships. Hidden slides are included.
+fn slide_part_names<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<String>> {
+    let Some(presentation_xml) = read_part(archive, "ppt/presentation.xml") else {
+        anyhow::bail!("Not a presentation: ppt/presentation.xml is missing");
+    };
+    let relationships = read_part(archive, "ppt/_rels/presentation.xml.rels")
+        .map(|xml| parse_relationships(&xml))
+        .unwrap_or_default();
+
+    let mut reader = Reader::from_str(&presentation_xml);
+    let mut slide_parts = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"sldId" => {
+                // sldId carries both a numeric `id` and the relationship `r:id`
+                let relationship_id = e
+                    .attributes()
+                    .filter_map(|a| a.ok())
+                    .find(|a| a.key.prefix().is_some() && a.key.local_name().as_ref() == b"id")
+                    .map(|a| String::from_utf8_lossy(&a.value).to_string());
+                if let Some(target) = relationship_id.and_then(|id| relationships.get(&id)) {
+                    slide_parts.push(resolve_part_name("ppt", target));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    Ok(slide_parts)
+}
+
+/// Runs workbooks and CSV files embedded in an OOXML package through the Excel and CSV
+/// extractors in memory. Objects count against the same size budget as archive members.
+pub(crate) fn extract_embedded_files<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    embeddings_folder: &str,
+    options: &ScanOptions,
+    depth: usize,
+    budget: &mut ArchiveBudget,
+) -> Vec<FileDetails> {
+    let mut embedded_files = Vec::new();
+    if depth >= MAX_ATTACHMENT_DEPTH {
+        return embedded_files;
+    }
+
+    for index in 0..archive.len() {
+        let Ok(mut part) = archive.by_index(index) else {
+            continue;
+        };
+        let Some(name) = part.name().strip_prefix(embeddings_folder).map(|name| name.to_string()) else {
+            continue;
+        };
+        if !is_embedded_dataset(&name) {
+            continue;
+        }
+        let size = part.size();
+        if !budget.reserve(size) {
+            break;
+        }
+
+        let created = zip_time(&part.last_modified());
+        let mut bytes = Vec::new();
+        if part.by_ref().take(size).read_to_end(&mut bytes).is_err() {
+            continue;
+        }
+
+        let details = process_embedded_file(&name, &bytes, &created, options, depth + 1, budget);
+        if details.file_type.is_some() {
+            embedded_files.push(details);
+        }
+    }
+
+    embedded_files
+}
+
+fn is_embedded_dataset(name: &str) -> bool {
+    is_embedded_workbook(name) || name.to_lowercase().ends_with(".csv")
+}
+
+fn is_embedded_workbook(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| WORKBOOK_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+#[derive(Default)]
+struct DocumentBody {
+    paragraph_count: usize,
+    tracked_change_count: usize,
+    tables: Vec<DocxTableMetadata>,
+}
+
+/// Per-table state while streaming `document.xml`; tables can nest inside cells.
+struct TableState {
+    index: usize,
+    row_count: usize,
+    in_first_row: bool,
+    columns: Vec<String>,
+    current_cell: Option<String>,
+}
+
+/// Streams a document or slide part collecting paragraph, revision and table information.
+fn scan_document_body<R: Read + Seek>(archive: &mut ZipArchive<R>, part_name: &str) -> Result<DocumentBody> {
+    let part = archive.by_name(part_name)?;
+    let mut reader = Reader::from_reader(BufReader::new(part));
+    let mut buffer = Vec::new();
+
+    let mut body = DocumentBody::default();
+    let mut tables: Vec<Option<DocxTableMetadata>> = Vec::new();
+    let mut stack: Vec<TableState> = Vec::new();
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event_into(&mut buffer) {
+            Ok(Event::Start(e)) => match e.local_name().as_ref() {
+                b"p" => body.paragraph_count += 1,
+                b"ins" | b"del" | b"moveFrom" | b"moveTo" => body.tracked_change_count += 1,
+                b"t" => in_text = true,
+                b"tbl" => {
+                    stack.push(TableState {
+                        index: tables.len(),
+                        row_count: 0,
+                        in_first_row: false,
+                        columns: Vec::new(),
+                        current_cell: None,
+                    });
+                    tables.push(None);
+                }
+                b"tr" => {
+                    if let Some(table) = stack.last_mut() {
+                        table.row_count += 1;
+                        table.in_first_row = table.row_count == 1;
+                    }
+                }
+                b"tc" => {
+                    if let Some(table) = stack.last_mut().filter(|table| table.in_first_row) {
+                        table.current_cell = Some(String::new());
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"p" => body.paragraph_count += 1,
+                b"ins" | b"del" => body.tracked_change_count += 1,
+                _ => {}
+            },
+            Ok(Event::Text(t)) if in_text => {
+                if let Some(cell) = stack.last_mut().and_then(|table| table.current_cell.as_mut()) {
+                    if let Ok(text) = t.unescape() {
+                        cell.push_str(&text);
+                    }
+                }
+            }
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"tc" => {
+                    if let Some(table) = stack.last_mut() {
+                        if let Some(cell) = table.current_cell.take() {
+                            table.columns.push(redact_nhs_numbers(cell.trim()));
+                        }
+                    }
+                }
+                b"tr" => {
+                    if let Some(table) = stack.last_mut() {
+                        table.in_first_row = false;
+                    }
+                }
+                b"tbl" => {
+                    if let Some(table) = stack.pop() {
+                        tables[table.index] = Some(DocxTableMetadata {
+                            header_row: table.columns,
+                            row_count: table.row_count,
+                        });
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+
+    body.tables = tables.into_iter().flatten().collect();
+    Ok(body)
+}
+
+/// Counts start or empty elements with the given local name in an XML stream.
+pub(crate) fn count_elements<B: std::io::BufRead>(source: B, local_name: &[u8]) -> usize {
+    let mut reader = Reader::from_reader(source);
+    let mut buffer = Vec::new();
+    let mut count = 0;
+
+    loop {
+        match reader.read_event_into(&mut buffer) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == local_name => {
+                count += 1;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+
+    count
+}
+
+/// Returns whether the workbook structure is locked, plus each sheet's name and relationship ID.
+fn parse_workbook_part(xml: &str) -> (bool, Vec<(String, String)>) {
+    let mut reader = Reader::from_str(xml);
+    let mut workbook_protected = false;
+    let mut sheets = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"workbookProtection" => {
+                    workbook_protected = ["lockStructure", "lockWindows"]
+                        .iter()
+                        .any(|key| is_true_attribute(&e, key.as_bytes()));
+                }
+                b"sheet" => {
+                    let name = attribute_value(&e, b"name");
+                    let relationship_id = attribute_value(&e, b"id");
+                    if let (Some(name), Some(relationship_id)) = (name, relationship_id) {
+                        sheets.push((name, relationship_id));
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    (workbook_protected, sheets)
+}
+
+/// Maps relationship IDs to targets from a `.rels` part.
+pub(crate) fn parse_relationships(xml: &str) -> HashMap<String, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut relationships = HashMap::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"Relationship" => {
+                if let (Some(id), Some(target)) =
+                    (attribute_value(&e, b"Id"), attribute_value(&e, b"Target"))
+                {
+                    relationships.insert(id, target);
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    relationships
+}
+
+/// Resolves a relationship target relative to the folder of its source part.
+pub(crate) fn resolve_part_name(base_folder: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut segments: Vec<&str> = base_folder.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => {}
+            _ => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Streams a worksheet part looking for an enforced `sheetProtection` element.
+/// The element follows `sheetData`, so the whole part has to be read.
+fn sheet_part_is_protected<R: Read + Seek>(archive: &mut ZipArchive<R>, part_name: &str) -> bool {
+    let Ok(part) = archive.by_name(part_name) else {
+        return false;
+    };
+
+    let mut reader = Reader::from_reader(BufReader::new(part));
+    let mut buffer = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buffer) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"sheetProtection" => {
+                return is_true_attribute(&e, b"sheet");
+            }
+            Ok(Event::Eof) | Err(_) => return false,
+            _ => {}
+        }
+        buffer.clear();
+    }
+}
+
+/// Looks up an attribute by local name, ignoring any namespace prefix.
+pub(crate) fn attribute_value(element: &quick_xml::events::BytesStart, key: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .filter_map(|a| a.ok())
+        .find(|a| a.key.local_name().as_ref() == key)
+        .map(|a| {
+            let raw = String::from_utf8_lossy(&a.value).to_string();
+            quick_xml::escape::unescape(&raw)
+                .map(|v| v.to_string())
+                .unwrap_or(raw)
+        })
+}
+
+fn is_true_attribute(element: &quick_xml::events::BytesStart, key: &[u8]) -> bool {
+    matches!(attribute_value(element, key).as_deref(), Some("1") | Some("true"))
+}
+
+/// Converts a W3CDTF timestamp to the same `YYYY-MM-DDTHH:MM` format used for `created`.
+/// Values that do not parse are returned unchanged.
+pub(crate) fn normalise_w3c_date(value: &str) -> String {
+    let value = value.trim();
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(datetime) => datetime
+            .with_timezone(&Utc)
+            .format("%Y-%m-%dT%H:%M")
+            .to_string(),
+        Err(_) => value.to_string(),
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::Result;
+use chrono::{FixedOffset, NaiveDate, TimeZone, Utc};
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::{redact_nhs_numbers, redact_person_name, PdfMetadata, PersonNameMode};
+
+// Operators that paint text; a page using any of them has an extractable text layer
+const TEXT_OPERATORS: [&str; 4] = ["Tj", "TJ", "'", "\""];
+
+// Guards against malformed name trees that reference themselves
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+// The trailer, or the cross-reference stream dictionary that replaces it, sits at the end of the file
+const TRAILER_REGION_SIZE: u64 = 64 * 1024;
+
+pub(crate) fn extract_pdf_metadata(path: &Path, person_names: PersonNameMode) -> Result<PdfMetadata> {
+    let document = Document::load(path)?;
+    Ok(pdf_metadata_from_document(document, person_names))
+}
+
+pub(crate) fn extract_pdf_metadata_from_bytes(
+    bytes: &[u8],
+    person_names: PersonNameMode,
+) -> Result<PdfMetadata> {
+    let document = Document::load_mem(bytes)?;
+    Ok(pdf_metadata_from_document(document, person_names))
+}
+
+/// Looks for an `/Encrypt` entry in the trailer region at the end of a PDF, for files that
+/// lopdf cannot load. Only an entry whose value is a reference or a dictionary counts, so page
+/// text that mentions `/Encrypt` does not.
+pub(crate) fn trailer_declares_encryption<R: Read + Seek>(reader: &mut R) -> bool {
+    let Ok(len) = reader.seek(SeekFrom::End(0)) else {
+        return false;
+    };
+    let mut tail = Vec::new();
+    if reader.seek(SeekFrom::Start(len.saturating_sub(TRAILER_REGION_SIZE))).is_err()
+        || reader.take(TRAILER_REGION_SIZE).read_to_end(&mut tail).is_err()
+    {
+        return false;
+    }
+
+    let key = b"/Encrypt";
+    tail.windows(key.len()).enumerate().any(|(position, window)| {
+        window == key
+            && tail[position + key.len()..]
+                .iter()
+                .find(|byte| !byte.is_ascii_whitespace())
+                .is_some_and(|&byte| byte.is_ascii_digit() || byte == b'<')
+    })
+}
+
+fn pdf_metadata_from_document(mut document: Document, person_names: PersonNameMode) -> PdfMetadata {
+    let encryption_filter = document
+        .get_encrypted()
+        .ok()
+        .and_then(|dict| dict.get(b"Filter").ok())
+        .and_then(|filter| filter.as_name_str().ok())
+        .map(|filter| filter.to_string());
+    let encrypted = document.is_encrypted();
+
+    // Files with only an owner password open with an empty user password, so their
+    // strings and streams can still be read
+    let readable = !encrypted || document.decrypt("").is_ok();
+
+    let pages = document.get_pages();
+    let page_count = pages.len();
+
+    let mut metadata = PdfMetadata {
+        page_count,
+        pdf_version: document.version.clone(),
+        title: None,
+        author: None,
+        producer: None,
+        creation_date: None,
+        encrypted,
+        encryption_filter,
+        text_layer: None,
+        pages_with_text: None,
+        image_only_pages: None,
+        attachment_count: count_attachments(&document),
+    };
+
+    if !readable {
+        return metadata;
+    }
+
+    if let Some(info) = info_dictionary(&document) {
+        let text = |key: &[u8]| {
+            info.get_deref(key, &document)
+                .ok()
+                .and_then(|value| value.as_str().ok())
+                .map(decode_pdf_text)
+                .filter(|value| !value.trim().is_empty())
+        };
+
+        metadata.title = text(b"Title").map(|title| redact_nhs_numbers(title.trim()));
+        metadata.author = text(b"Author").map(|author| redact_person_name(&author, person_names));
+        metadata.producer = text(b"Producer").map(|producer| redact_nhs_numbers(producer.trim()));
+        metadata.creation_date = text(b"CreationDate").map(|date| normalise_pdf_date(&date));
+    }
+
+    let mut pages_with_text = 0;
+    let mut image_only_pages = 0;
+
+    for page_id in pages.values() {
+        let has_text = document
+            .get_page_content(*page_id)
+            .ok()
+            .and_then(|content| Content::decode(&content).ok())
+            .map(|content| {
+                content
+                    .operations
+                    .iter()
+                    .any(|operation| TEXT_OPERATORS.contains(&operation.operator.as_str()))
+            })
+            .unwrap_or(false);
+
+        if has_text {
+            pages_with_text += 1;
+        } else if document
+            .get_page_images(*page_id)
+            .map(|images| !images.is_empty())
+            .unwrap_or(false)
+        {
+            image_only_pages += 1;
+        }
+    }
+
+    metadata.text_layer = Some(
+        match pages_with_text {
+            0 => "none",
+            n if n == page_count => "full",
+            _ => "partial",
+        }
+        .to_string(),
+    );
+    metadata.pages_with_text = Some(pages_with_text);
+    metadata.image_only_pages = Some(image_only_pages);
+
+    metadata
+}
+
+fn info_dictionary(document: &Document) -> Option<&Dictionary> {
+    match document.trailer.get(b"Info").ok()? {
+        Object::Reference(id) => document.get_dictionary(*id).ok(),
+        Object::Dictionary(dict) => Some(dict),
+        _ => None,
+    }
+}
+
+/// Counts embedded files in the catalog's EmbeddedFiles name tree plus
+/// FileAttachment annotations on pages.
+fn count_attachments(document: &Document) -> usize {
+    let embedded = document
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get_deref(b"Names", document).ok())
+        .and_then(|names| names.as_dict().ok())
+        .and_then(|names| names.get_deref(b"EmbeddedFiles", document).ok())
+        .and_then(|tree| tree.as_dict().ok())
+        .map(|tree| count_name_tree_entries(document, tree, 0))
+        .unwrap_or(0);
+
+    let annotations: usize = document
+        .page_iter()
+        .filter_map(|page_id| document.get_page_annotations(page_id).ok())
+        .flatten()
+        .filter(|annotation| {
+            annotation
+                .get(b"Subtype")
+                .and_then(|subtype| subtype.as_name())
+                .map(|subtype| subtype == b"FileAttachment")
+                .unwrap_or(false)
+        })
+        .count();
+
+    embedded + annotations
+}
+
+fn count_name_tree_entries(document: &Document, node: &Dictionary, depth: usize) -> usize {
+    if depth > MAX_NAME_TREE_DEPTH {
+        return 0;
+    }
+
+    // Leaf nodes hold [key1 value1 key2 value2 ...]
+    let leaves = node
+        .get_deref(b"Names", document)
+        .and_then(|names| names.as_array())
+        .map(|names| names.len() / 2)
+        .unwrap_or(0);
+
+    let kids: usize = node
+        .get_deref(b"Kids", document)
+        .and_then(|kids| kids.as_array())
+        .map(|kids| {
+            kids.iter()
+                .filter_map(|kid| document.dereference(kid).ok())
+                .filter_map(|(_, kid)| kid.as_dict().ok())
+                .map(|kid| count_name_tree_entries(document, kid, depth + 1))
+                .sum()
+        })
+        .unwrap_or(0);
+
+    leaves + kids
+}
+
+/// Decodes a PDF text string, which is either UTF-16BE with a byte order mark,
+/// UTF-8 with a byte order mark (PDF 2.0) or PDFDocEncoding (treated as Latin-1).
+fn decode_pdf_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(utf8).to_string();
+    }
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Converts a PDF date (`D:YYYYMMDDHHmmSSOHH'mm'`) to the `YYYY-MM-DDTHH:MM` format
+/// used elsewhere, in UTC. Values that do not parse are returned unchanged.
+fn normalise_pdf_date(value: &str) -> String {
+    let trimmed = value.trim();
+    let raw = trimmed.strip_prefix("D:").unwrap_or(trimmed);
+    let digits: String = raw.chars().take_while(|c| c.is_ascii_digit()).collect();
+
+    let field = |start: usize, len: usize, default: u32| {
+        digits
+            .get(start..start + len)
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(default)
+    };
+
+    if digits.len() < 4 {
+        return trimmed.to_string();
+    }
+
+    let Some(naive) = NaiveDate::from_ymd_opt(field(0, 4, 0) as i32, field(4, 2, 1), field(6, 2, 1))
+        .and_then(|date| date.and_hms_opt(field(8, 2, 0), field(10, 2, 0), field(12, 2, 0)))
+    else {
+        return trimmed.to_string();
+    };
+
+    // Offset follows the digits as Z, +HH'mm' or -HH'mm'; local time is treated as UTC
+    let rest = &raw[digits.len()..];
+    let offset_seconds = match rest.chars().next() {
+        Some(sign @ ('+' | '-')) => {
+            let offset_digits: String = rest[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+            let hours = offset_digits.get(0..2).and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
+            let minutes = offset_digits.get(2..4).and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
+            let seconds = hours * 3600 + minutes * 60;
+            if sign == '-' {
+                -seconds
+            } else {
+                seconds
+            }
+        }
+        _ => 0,
+    };
+
+    FixedOffset::east_opt(offset_seconds)
+        .and_then(|offset| offset.from_local_datetime(&naive).single())
+        .map(|datetime| datetime.with_timezone(&Utc).format("%Y-%m-%dT%H:%M").to_string())
+        .unwrap_or_else(|| trimmed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const CONTENT: &str = "BT /F1 12 Tf (/Encrypt) Tj ET";
+    const ENCRYPT_TRAILER: &str = "/Encrypt 5 0 R /ID [<0123456789abcdef0123456789abcdef> <0123456789abcdef0123456789abcdef>]";
+
+    /// A one-page PDF whose page text mentions `/Encrypt`, with an encryption dictionary
+    /// referenced from the trailer when `encrypted` is set. `broken_xref` points `startxref`
+    /// past the cross-reference table so that lopdf cannot load the file.
+    fn pdf_bytes(encrypted: bool, broken_xref: bool) -> Vec<u8> {
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R >>".to_string(),
+            format!("<< /Length {} >>\nstream\n{}\nendstream", CONTENT.len(), CONTENT),
+        ];
+        if encrypted {
+            let password = "00".repeat(32);
+            objects.push(format!("<< /Filter /Standard /V 1 /R 2 /O <{password}> /U <{password}> /P -4 >>"));
+        }
+
+        let mut bytes = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(bytes.len());
+            bytes.extend(format!("{} 0 obj\n{}\nendobj\n", index + 1, object).into_bytes());
+        }
+        let xref = bytes.len() + if broken_xref { 7 } else { 0 };
+        bytes.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+        for offset in offsets {
+            bytes.extend(format!("{offset:010} 00000 n \n").into_bytes());
+        }
+        let extra = if encrypted { ENCRYPT_TRAILER } else { "" };
+        bytes.extend(
+            format!("trailer\n<< /Size {} /Root 1 0 R {extra} >>\nstartxref\n{xref}\n%%EOF\n", objects.len() + 1)
+                .into_bytes(),
+        );
+        bytes
+    }
+
+    #[test]
+    fn encryption_comes_from_the_parsed_trailer() {
+        let encrypted = extract_pdf_metadata_from_bytes(&pdf_bytes(true, false), PersonNameMode::Keep).unwrap();
+        assert!(encrypted.encrypted);
+        assert_eq!(encrypted.encryption_filter.as_deref(), Some("Standard"));
+
+        // Page text mentioning /Encrypt does not make a file encrypted
+        let plain = extract_pdf_metadata_from_bytes(&pdf_bytes(false, false), PersonNameMode::Keep).unwrap();
+        assert!(!plain.encrypted);
+        assert_eq!(plain.page_count, 1);
+    }
+
+    #[test]
+    fn unloadable_files_fall_back_to_the_trailer_region() {
+        let encrypted = pdf_bytes(true, true);
+        assert!(extract_pdf_metadata_from_bytes(&encrypted, PersonNameMode::Keep).is_err());
+        assert!(trailer_declares_encryption(&mut Cursor::new(&encrypted)));
+
+        let plain = pdf_bytes(false, true);
+        assert!(extract_pdf_metadata_from_bytes(&plain, PersonNameMode::Keep).is_err());
+        assert!(!trailer_declares_encryption(&mut Cursor::new(&plain)));
+        assert!(!trailer_declares_encryption(&mut Cursor::new(b"")));
+    }
+}
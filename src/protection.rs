@@ -0,0 +1,86 @@
+use cfb::CompoundFile;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+const OLE2_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+// BIFF record types used to spot FILEPASS in the .xls workbook globals
+const BIFF_FILEPASS: u16 = 0x002F;
+const BIFF_EOF: u16 = 0x000A;
+
+/// Detects file-level encryption or rights management of OLE2-based files.
+/// Returns the protection type (`password`, `irm`) when the file is protected.
+/// PDF encryption is read from the PDF metadata instead.
+pub(crate) fn detect_protection(path: &Path) -> Option<String> {
+    if !has_ole2_signature(path) {
+        return None;
+    }
+
+    let mut compound = cfb::open(path).ok()?;
+    detect_ole_protection(&mut compound)
+}
+
+fn has_ole2_signature(path: &Path) -> bool {
+    let mut signature = [0u8; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .map(|_| signature == OLE2_SIGNATURE)
+        .unwrap_or(false)
+}
+
+/// Inspects an OLE2 compound file for encrypted OOXML packages, IRM protection
+/// and legacy Excel/Word password encryption.
+pub(crate) fn detect_ole_protection<F: Read + Seek>(compound: &mut CompoundFile<F>) -> Option<String> {
+    // Encrypted OOXML files are wrapped in an OLE2 container holding EncryptedPackage;
+    // the data space transform tells password encryption apart from rights management
+    if compound.exists("/EncryptedPackage") {
+        if compound.exists("/\u{6}DataSpaces/TransformInfo/DRMEncryptedTransform") {
+            return Some("irm".to_string());
+        }
+        return Some("password".to_string());
+    }
+
+    if compound.is_stream("/Workbook") || compound.is_stream("/Book") {
+        let stream_name = if compound.is_stream("/Workbook") { "/Workbook" } else { "/Book" };
+        if let Ok(mut stream) = compound.open_stream(stream_name) {
+            if biff_has_filepass(&mut stream) {
+                return Some("password".to_string());
+            }
+        }
+    }
+
+    if let Ok(mut stream) = compound.open_stream("/WordDocument") {
+        // fEncrypted is bit 8 of the FIB flags word at offset 0x0A
+        let mut fib = [0u8; 12];
+        if stream.read_exact(&mut fib).is_ok() && fib[0x0B] & 0x01 != 0 {
+            return Some("password".to_string());
+        }
+    }
+
+    None
+}
+
+/// Walks the BIFF records of the workbook globals looking for FILEPASS.
+fn biff_has_filepass<R: Read>(stream: &mut R) -> bool {
+    let mut header = [0u8; 4];
+    // FILEPASS follows BOF closely; give up after a reasonable number of records
+    for _ in 0..64 {
+        if stream.read_exact(&mut header).is_err() {
+            return false;
+        }
+        let record_type = u16::from_le_bytes([header[0], header[1]]);
+        let record_len = u16::from_le_bytes([header[2], header[3]]) as u64;
+
+        match record_type {
+            BIFF_FILEPASS => return true,
+            BIFF_EOF => return false,
+            _ => {
+                if std::io::copy(&mut stream.take(record_len), &mut std::io::sink()).is_err() {
+                    return false;
+                }
+            }
+        }
+    }
+    false
+}
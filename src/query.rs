@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use strsim::jaro_winkler;
+
+use crate::synonyms::ColumnSynonyms;
+use crate::{normalise_column_name, round_similarity, ColumnIndexEntry};
+
+/// The part of a scan output the query reads; everything else is ignored.
+#[derive(Deserialize)]
+struct ScanIndex {
+    #[serde(default)]
+    column_index: Option<Vec<ColumnIndexEntry>>,
+}
+
+/// Looks up columns in the `column_index` of a scan output and prints every indexed
+/// column whose normalised name matches exactly or fuzzily, best matches first.
+pub(crate) fn run_query(
+    scan: &Path,
+    columns: &[String],
+    threshold: f64,
+    synonyms: Option<&ColumnSynonyms>,
+) -> Result<()> {
+    if !(0.0..=1.0).contains(&threshold) {
+        anyhow::bail!("Threshold must be between 0.0 and 1.0: {}", threshold);
+    }
+
+    let json = fs::read_to_string(scan).context(format!("Failed to read scan output: {:?}", scan))?;
+    let index: ScanIndex = serde_json::from_str(&json).context(format!("Not a scan output file: {:?}", scan))?;
+    let Some(column_index) = index.column_index else {
+        anyhow::bail!("{:?} has no column_index; rerun the scan with this version", scan);
+    };
+
+    for (position, column) in columns.iter().enumerate() {
+        // Queries are normalised the same way as the index
+        let normalised = match synonyms {
+            Some(synonyms) => synonyms.canonical_name(column),
+            None => normalise_column_name(column),
+        };
+
+        let matches = find_matches(&column_index, &normalised, threshold);
+
+        if position > 0 {
+            println!();
+        }
+        println!("{} ({}): {} matching column(s)", column, normalised, matches.len());
+        for (similarity, entry) in matches {
+            let kind = if entry.column == normalised {
+                "exact".to_string()
+            } else {
+                format!("similarity {}", round_similarity(similarity))
+            };
+            println!(
+                "  {} [{}] in {} dataset(s), spelled {}",
+                entry.column,
+                kind,
+                entry.count,
+                entry.spellings.join(", ")
+            );
+            for source in &entry.sources {
+                println!("    {}", source);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the indexed columns whose names match a normalised query name at or above the
+/// threshold, as (similarity, entry), best matches first.
+fn find_matches<'a>(
+    column_index: &'a [ColumnIndexEntry],
+    normalised: &str,
+    threshold: f64,
+) -> Vec<(f64, &'a ColumnIndexEntry)> {
+    let mut matches: Vec<(f64, &ColumnIndexEntry)> = column_index
+        .iter()
+        .map(|entry| {
+            let similarity = if entry.column == normalised { 1.0 } else { jaro_winkler(normalised, &entry.column) };
+            (similarity, entry)
+        })
+        .filter(|(similarity, _)| *similarity >= threshold)
+        .collect();
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.column.cmp(&b.1.column)));
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(column: &str, spellings: &[&str], sources: &[&str]) -> ColumnIndexEntry {
+        ColumnIndexEntry {
+            column: column.to_string(),
+            count: sources.len(),
+            spellings: spellings.iter().map(|spelling| spelling.to_string()).collect(),
+            sources: sources.iter().map(|source| source.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn queries_a_serialized_column_index() {
+        let column_index = vec![
+            entry("dateofbirth", &["date_of_birth"], &["c.csv"]),
+            entry("dob", &["DOB"], &["a.csv"]),
+            entry("nhsno", &["NHS_No"], &["b.csv"]),
+            entry("nhsnumber", &["NHS Number", "nhs_number"], &["a.csv", "b.xlsx#Sheet1"]),
+        ];
+        let scan = serde_json::json!({ "scan_root": "/data", "column_index": column_index });
+        let index: ScanIndex = serde_json::from_str(&scan.to_string()).unwrap();
+        let column_index = index.column_index.unwrap();
+
+        let matches: Vec<(&str, f64)> = find_matches(&column_index, &normalise_column_name("NHS-Number"), 0.8)
+            .into_iter()
+            .map(|(similarity, entry)| (entry.column.as_str(), round_similarity(similarity)))
+            .collect();
+        assert_eq!(matches[0], ("nhsnumber", 1.0));
+        assert_eq!(matches.iter().map(|(column, _)| *column).collect::<Vec<_>>(), ["nhsnumber", "nhsno"]);
+        assert!(matches[1].1 < 1.0);
+
+        let synonyms = ColumnSynonyms::parse("dob -> date of birth", Path::new("synonyms.txt")).unwrap();
+        let matches = find_matches(&column_index, &synonyms.canonical_name("DOB"), 1.0);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].1.sources, ["c.csv"]);
+
+        let path = std::env::temp_dir().join(format!("column_index_query_{}.json", std::process::id()));
+        fs::write(&path, scan.to_string()).unwrap();
+        let result = run_query(&path, &["NHS Number".to_string()], 0.85, None);
+        let out_of_range = run_query(&path, &["NHS Number".to_string()], 1.5, None);
+        fs::write(&path, r#"{"scan_root": "/data"}"#).unwrap();
+        let old_scan = run_query(&path, &["NHS Number".to_string()], 0.85, None);
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_ok());
+        assert!(out_of_range.is_err());
+        assert!(old_scan.unwrap_err().to_string().contains("has no column_index"));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+use strsim::jaro_winkler;
+
+use crate::{
+    round_similarity, ColumnOrderVariant, ColumnRename, PartialColumn, SchemaDiff, COLUMN_MATCH_THRESHOLD,
+};
+
+/// Compares the columns of a fuzzy group's members, given as (source, columns) in the
+/// order the group lists its sources. Column names are compared exactly.
+pub(crate) fn build_schema_diff(members: &[(&str, &[String])]) -> SchemaDiff {
+    // Each member's columns without repeats, keeping their first position
+    let member_columns: Vec<Vec<&str>> = members
+        .iter()
+        .map(|(_, columns)| {
+            let mut seen = HashSet::new();
+            columns
+                .iter()
+                .map(|column| column.as_str())
+                .filter(|column| seen.insert(*column))
+                .collect()
+        })
+        .collect();
+
+    let mut holders: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (member, columns) in member_columns.iter().enumerate() {
+        for column in columns {
+            holders.entry(column).or_default().push(member);
+        }
+    }
+
+    let mut common_columns: Vec<String> = Vec::new();
+    let mut partial: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (column, members_with) in holders {
+        if members_with.len() == members.len() {
+            common_columns.push(column.to_string());
+        } else {
+            partial.push((column, members_with));
+        }
+    }
+    common_columns.sort();
+    partial.sort_by(|a, b| a.0.cmp(b.0));
+
+    let probable_renames = find_renames(&partial);
+
+    // Distinct orders of the common columns, reported only when members disagree
+    let common: HashSet<&str> = common_columns.iter().map(|column| column.as_str()).collect();
+    let mut order_variants: Vec<ColumnOrderVariant> = Vec::new();
+    for (member, columns) in member_columns.iter().enumerate() {
+        let order: Vec<String> = columns
+            .iter()
+            .filter(|column| common.contains(*column))
+            .map(|column| column.to_string())
+            .collect();
+        match order_variants.iter_mut().find(|variant| variant.order == order) {
+            Some(variant) => variant.sources.push(members[member].0.to_string()),
+            None => order_variants.push(ColumnOrderVariant {
+                order,
+                sources: vec![members[member].0.to_string()],
+            }),
+        }
+    }
+    if order_variants.len() < 2 {
+        order_variants.clear();
+    }
+
+    SchemaDiff {
+        common_columns,
+        partial_columns: partial
+            .into_iter()
+            .map(|(column, members_with)| PartialColumn {
+                column: column.to_string(),
+                sources: members_with.iter().map(|&member| members[member].0.to_string()).collect(),
+            })
+            .collect(),
+        probable_renames,
+        order_variants,
+    }
+}
+
+/// Pairs up partial columns that never appear in the same member and whose lowercase
+/// names are similar, best matches first. Each rename points from the less widely held
+/// name to the more widely held one, so the results read as a harmonisation mapping.
+fn find_renames(partial: &[(&str, Vec<usize>)]) -> Vec<ColumnRename> {
+    let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+    for (a, (column_a, members_a)) in partial.iter().enumerate() {
+        for (b, (column_b, members_b)) in partial.iter().enumerate().skip(a + 1) {
+            if members_a.iter().any(|member| members_b.contains(member)) {
+                continue;
+            }
+            let similarity = jaro_winkler(&column_a.to_lowercase(), &column_b.to_lowercase());
+            if similarity > COLUMN_MATCH_THRESHOLD {
+                // Ties keep the alphabetically first name as the target
+                let (from, to) = if members_b.len() > members_a.len() { (a, b) } else { (b, a) };
+                candidates.push((similarity, from, to));
+            }
+        }
+    }
+    candidates.sort_by(|x, y| y.0.total_cmp(&x.0).then(x.1.cmp(&y.1)).then(x.2.cmp(&y.2)));
+
+    let mut sources = HashSet::new();
+    let mut targets = HashSet::new();
+    let mut renames = Vec::new();
+    for (similarity, from, to) in candidates {
+        // A name is renamed at most once and never both renamed and a rename target
+        if sources.contains(&from) || targets.contains(&from) || sources.contains(&to) {
+            continue;
+        }
+        sources.insert(from);
+        targets.insert(to);
+        renames.push(ColumnRename {
+            from: partial[from].0.to_string(),
+            to: partial[to].0.to_string(),
+            similarity: round_similarity(similarity),
+        });
+    }
+    renames.sort_by(|a, b| a.from.cmp(&b.from));
+    renames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_a_renamed_column_and_a_reordered_one() {
+        let a = columns(&["id", "name", "dob", "postcode"]);
+        let b = columns(&["id", "dob", "name", "post_code", "dob"]);
+        let diff = build_schema_diff(&[("a.csv", &a), ("b.csv", &b)]);
+
+        assert_eq!(diff.common_columns, ["dob", "id", "name"]);
+        let partial: Vec<(&str, &[String])> = diff
+            .partial_columns
+            .iter()
+            .map(|partial| (partial.column.as_str(), partial.sources.as_slice()))
+            .collect();
+        assert_eq!(partial, [("post_code", &["b.csv".to_string()][..]), ("postcode", &["a.csv".to_string()][..])]);
+
+        // Both names are held once, so the alphabetically first is the target
+        assert_eq!(diff.probable_renames.len(), 1);
+        let rename = &diff.probable_renames[0];
+        assert_eq!((rename.from.as_str(), rename.to.as_str()), ("postcode", "post_code"));
+        assert!(rename.similarity > COLUMN_MATCH_THRESHOLD);
+
+        let variants: Vec<(&[String], &[String])> = diff
+            .order_variants
+            .iter()
+            .map(|variant| (variant.order.as_slice(), variant.sources.as_slice()))
+            .collect();
+        assert_eq!(
+            variants,
+            [
+                (&columns(&["id", "name", "dob"])[..], &columns(&["a.csv"])[..]),
+                (&columns(&["id", "dob", "name"])[..], &columns(&["b.csv"])[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_point_to_the_more_widely_held_name() {
+        let a = columns(&["id", "NHS_Number"]);
+        let b = columns(&["id", "nhs_number"]);
+        let c = columns(&["id", "nhs_number"]);
+        let diff = build_schema_diff(&[("a.csv", &a), ("b.csv", &b), ("c.csv", &c)]);
+
+        let renames: Vec<(&str, &str)> =
+            diff.probable_renames.iter().map(|rename| (rename.from.as_str(), rename.to.as_str())).collect();
+        assert_eq!(renames, [("NHS_Number", "nhs_number")]);
+        assert_eq!(diff.probable_renames[0].similarity, 1.0);
+        assert!(diff.order_variants.is_empty());
+    }
+}
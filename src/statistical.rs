@@ -0,0 +1,789 @@
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{calculate_column_similarity_hash, redact_nhs_numbers, StatisticalMetadata};
+
+// Dictionaries are small; anything larger than this is a corrupt length field
+const MAX_RECORD_SIZE: usize = 64 * 1024 * 1024;
+
+const SAS_MAGIC: [u8; 32] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC2, 0xEA, 0x81, 0x60,
+    0xB3, 0x14, 0x11, 0xCF, 0xBD, 0x92, 0x08, 0x00, 0x09, 0xC7, 0x31, 0x8C, 0x18, 0x1F, 0x10, 0x11,
+];
+
+/// Returns the statistical package format for a file extension, or `None` if it is not one.
+pub(crate) fn statistical_format(extension: &str) -> Option<&'static str> {
+    match extension {
+        "sav" | "zsav" => Some("spss"),
+        "dta" => Some("stata"),
+        "sas7bdat" => Some("sas"),
+        _ => None,
+    }
+}
+
+/// Reads variable names and labels, value-label presence, case counts and creation details
+/// from the dictionary or header of an SPSS, Stata or SAS dataset, without reading the data.
+pub(crate) fn extract_statistical_metadata<R: Read + Seek>(reader: &mut R, format: &str) -> Result<StatisticalMetadata> {
+    match format {
+        "spss" => spss_metadata(reader),
+        "stata" => stata_metadata(reader),
+        "sas" => sas_metadata(reader),
+        _ => anyhow::bail!("Unsupported statistical format: {}", format),
+    }
+}
+
+/// One variable as read from a dictionary, before redaction.
+struct Variable {
+    name: String,
+    label: Option<String>,
+    value_labels: bool,
+}
+
+fn new_metadata(format: &str, variables: Vec<Variable>) -> StatisticalMetadata {
+    let columns: Vec<String> = variables.iter().map(|variable| redact_nhs_numbers(&variable.name)).collect();
+    let value_labelled_columns = variables
+        .iter()
+        .zip(&columns)
+        .filter(|(variable, _)| variable.value_labels)
+        .map(|(_, column)| column.clone())
+        .collect();
+
+    StatisticalMetadata {
+        format: format.to_string(),
+        format_version: None,
+        creator: None,
+        created: None,
+        modified: None,
+        file_label: None,
+        case_count: None,
+        canonical_columns: Vec::new(),
+        column_similarity_hash: calculate_column_similarity_hash(&columns),
+        variable_labels: variables
+            .iter()
+            .map(|variable| variable.label.as_deref().map(redact_nhs_numbers))
+            .collect(),
+        columns,
+        value_labelled_columns,
+    }
+}
+
+/// Decodes a fixed-width text field: stops at the first NUL, falls back to Latin-1 for
+/// files written in a legacy code page and trims padding. Empty fields become `None`.
+fn decode_text(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let bytes = &bytes[..end];
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Reads a record whose length may come from the file. The buffer grows as bytes arrive,
+/// so a corrupt length fails at the end of the file instead of allocating up front.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>> {
+    if len > MAX_RECORD_SIZE {
+        anyhow::bail!("Record too large");
+    }
+    let mut buffer = Vec::new();
+    reader.by_ref().take(len as u64).read_to_end(&mut buffer)?;
+    if buffer.len() != len {
+        anyhow::bail!("File ends inside a record of {} bytes", len);
+    }
+    Ok(buffer)
+}
+
+fn format_datetime(datetime: NaiveDateTime) -> String {
+    datetime.format("%Y-%m-%dT%H:%M").to_string()
+}
+
+/// Reads integers in the byte order declared by the file.
+#[derive(Clone, Copy)]
+struct ByteOrder {
+    little_endian: bool,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn u64(self, bytes: &[u8]) -> u64 {
+        let mut array = [0u8; 8];
+        array.copy_from_slice(&bytes[..8]);
+        if self.little_endian {
+            u64::from_le_bytes(array)
+        } else {
+            u64::from_be_bytes(array)
+        }
+    }
+
+    fn f64(self, bytes: &[u8]) -> f64 {
+        f64::from_bits(self.u64(bytes))
+    }
+
+    fn read_i32<R: Read>(self, reader: &mut R) -> Result<i32> {
+        Ok(self.u32(&read_bytes(reader, 4)?) as i32)
+    }
+}
+
+// SPSS .sav: a 176-byte header followed by dictionary records up to the type 999 terminator
+const SPSS_HEADER_LEN: usize = 176;
+const SPSS_VARIABLE_RECORD: i32 = 2;
+const SPSS_VALUE_LABEL_RECORD: i32 = 3;
+const SPSS_VALUE_LABEL_VARIABLES_RECORD: i32 = 4;
+const SPSS_DOCUMENT_RECORD: i32 = 6;
+const SPSS_EXTENSION_RECORD: i32 = 7;
+const SPSS_DICTIONARY_END: i32 = 999;
+const SPSS_LONG_NAMES_SUBTYPE: i32 = 13;
+
+fn spss_metadata<R: Read + Seek>(reader: &mut R) -> Result<StatisticalMetadata> {
+    reader.seek(SeekFrom::Start(0))?;
+    let header = read_bytes(reader, SPSS_HEADER_LEN)?;
+    if &header[..4] != b"$FL2" && &header[..4] != b"$FL3" {
+        anyhow::bail!("Not an SPSS system file");
+    }
+
+    // The layout code is 2 or 3; reading it in the wrong byte order gives a huge number
+    let little = ByteOrder { little_endian: true };
+    let order = ByteOrder {
+        little_endian: matches!(little.u32(&header[64..68]), 2 | 3),
+    };
+
+    // Each variable record fills one 8-byte slot; value labels refer to slots, 1-based
+    let mut variables: Vec<(usize, Variable)> = Vec::new();
+    let mut slot = 0;
+    let mut labelled_slots = HashSet::new();
+    let mut long_names = HashMap::new();
+
+    loop {
+        match order.read_i32(reader)? {
+            SPSS_VARIABLE_RECORD => {
+                let fields = read_bytes(reader, 20)?;
+                let name = read_bytes(reader, 8)?;
+                let variable_type = order.u32(&fields[0..4]) as i32;
+                let has_label = order.u32(&fields[4..8]) == 1;
+                let missing_values = (order.u32(&fields[8..12]) as i32).unsigned_abs() as usize;
+                slot += 1;
+
+                let label = if has_label {
+                    let len = order.read_i32(reader)?.max(0) as usize;
+                    let padded = len.div_ceil(4) * 4;
+                    decode_text(&read_bytes(reader, padded)?[..len])
+                } else {
+                    None
+                };
+                read_bytes(reader, missing_values * 8)?;
+
+                // Type -1 records continue a long string variable
+                if variable_type != -1 {
+                    variables.push((
+                        slot,
+                        Variable {
+                            name: decode_text(&name).unwrap_or_default(),
+                            label,
+                            value_labels: false,
+                        },
+                    ));
+                }
+            }
+            SPSS_VALUE_LABEL_RECORD => {
+                let count = order.read_i32(reader)?.max(0) as usize;
+                for _ in 0..count {
+                    let value_and_len = read_bytes(reader, 9)?;
+                    let len = value_and_len[8] as usize;
+                    // The length byte and label are padded to a multiple of 8
+                    read_bytes(reader, (len + 1).div_ceil(8) * 8 - 1)?;
+                }
+            }
+            SPSS_VALUE_LABEL_VARIABLES_RECORD => {
+                let count = order.read_i32(reader)?.max(0) as usize;
+                let indexes = read_bytes(reader, count * 4)?;
+                labelled_slots.extend(indexes.chunks_exact(4).map(|index| order.u32(index) as usize));
+            }
+            SPSS_DOCUMENT_RECORD => {
+                let lines = order.read_i32(reader)?.max(0) as usize;
+                read_bytes(reader, lines * 80)?;
+            }
+            SPSS_EXTENSION_RECORD => {
+                let subtype = order.read_i32(reader)?;
+                let size = order.read_i32(reader)?.max(0) as usize;
+                let count = order.read_i32(reader)?.max(0) as usize;
+                let data = read_bytes(reader, size.saturating_mul(count))?;
+                // Long names map the 8-character short names to the names users see
+                if subtype == SPSS_LONG_NAMES_SUBTYPE {
+                    let text = String::from_utf8_lossy(&data);
+                    for pair in text.split('\t') {
+                        if let Some((short, long)) = pair.split_once('=') {
+                            long_names.insert(short.trim().to_string(), long.trim().to_string());
+                        }
+                    }
+                }
+            }
+            SPSS_DICTIONARY_END => break,
+            other => anyhow::bail!("Unknown SPSS record type {}", other),
+        }
+    }
+
+    let variables = variables
+        .into_iter()
+        .map(|(slot, mut variable)| {
+            if let Some(long) = long_names.get(&variable.name) {
+                variable.name = long.clone();
+            }
+            variable.value_labels = labelled_slots.contains(&slot);
+            variable
+        })
+        .collect();
+
+    let mut metadata = new_metadata("spss", variables);
+    metadata.format_version = Some(String::from_utf8_lossy(&header[..4]).to_string());
+    metadata.creator = decode_text(&header[4..64]).map(|product| product.trim_start_matches("@(#)").trim().to_string());
+    let case_count = order.u32(&header[80..84]) as i32;
+    metadata.case_count = (case_count >= 0).then_some(case_count as u64);
+    metadata.created = decode_text(&header[92..101])
+        .zip(decode_text(&header[101..109]))
+        .and_then(|(date, time)| {
+            NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%d %b %y %H:%M:%S").ok()
+        })
+        .map(format_datetime);
+    metadata.file_label = decode_text(&header[109..173]).map(|label| redact_nhs_numbers(&label));
+    Ok(metadata)
+}
+
+fn stata_metadata<R: Read + Seek>(reader: &mut R) -> Result<StatisticalMetadata> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    // Releases 117 and later wrap each section in XML-like tags
+    if first[0] == b'<' {
+        stata_tagged_metadata(reader)
+    } else {
+        stata_legacy_metadata(reader)
+    }
+}
+
+/// Reads the binary header used by Stata releases 110 to 115 (Stata 7 to 12).
+fn stata_legacy_metadata<R: Read>(reader: &mut R) -> Result<StatisticalMetadata> {
+    let header = read_bytes(reader, 4)?;
+    let release = header[0];
+    if !(110..=115).contains(&release) {
+        anyhow::bail!("Unsupported Stata release {}", release);
+    }
+    let order = ByteOrder {
+        little_endian: header[1] == 2,
+    };
+
+    let nvar = order.u16(&read_bytes(reader, 2)?) as usize;
+    let nobs = order.u32(&read_bytes(reader, 4)?) as u64;
+    let data_label = read_bytes(reader, 81)?;
+    let timestamp = read_bytes(reader, 18)?;
+
+    read_bytes(reader, nvar)?; // typlist
+    let names = read_bytes(reader, nvar * 33)?;
+    read_bytes(reader, 2 * (nvar + 1))?; // srtlist
+    read_bytes(reader, nvar * if release >= 114 { 49 } else { 12 })?; // fmtlist
+    let value_label_names = read_bytes(reader, nvar * 33)?;
+    let labels = read_bytes(reader, nvar * 81)?;
+
+    let mut metadata = stata_variables(nvar, &names, 33, &value_label_names, 33, &labels, 81);
+    metadata.format_version = Some(release.to_string());
+    metadata.case_count = Some(nobs);
+    metadata.file_label = decode_text(&data_label).map(|label| redact_nhs_numbers(&label));
+    metadata.created = decode_text(&timestamp).and_then(|timestamp| stata_timestamp(&timestamp));
+    Ok(metadata)
+}
+
+/// Reads the tagged header used by Stata releases 117 to 119 (Stata 13 onwards).
+fn stata_tagged_metadata<R: Read>(reader: &mut R) -> Result<StatisticalMetadata> {
+    expect_tag(reader, "<stata_dta><header><release>")?;
+    let release: u32 = String::from_utf8_lossy(&read_bytes(reader, 3)?).parse()?;
+    if !(117..=119).contains(&release) {
+        anyhow::bail!("Unsupported Stata release {}", release);
+    }
+    expect_tag(reader, "</release><byteorder>")?;
+    let order = ByteOrder {
+        little_endian: read_bytes(reader, 3)? == b"LSF",
+    };
+    expect_tag(reader, "</byteorder><K>")?;
+    let nvar = if release == 119 {
+        order.u32(&read_bytes(reader, 4)?) as usize
+    } else {
+        order.u16(&read_bytes(reader, 2)?) as usize
+    };
+    expect_tag(reader, "</K><N>")?;
+    let nobs = if release == 117 {
+        order.u32(&read_bytes(reader, 4)?) as u64
+    } else {
+        order.u64(&read_bytes(reader, 8)?)
+    };
+    expect_tag(reader, "</N><label>")?;
+    let label_len = if release == 117 {
+        read_bytes(reader, 1)?[0] as usize
+    } else {
+        order.u16(&read_bytes(reader, 2)?) as usize
+    };
+    let data_label = read_bytes(reader, label_len)?;
+    expect_tag(reader, "</label><timestamp>")?;
+    let timestamp_len = read_bytes(reader, 1)?[0] as usize;
+    let timestamp = read_bytes(reader, timestamp_len)?;
+    expect_tag(reader, "</timestamp></header><map>")?;
+    read_bytes(reader, 14 * 8)?;
+
+    // Release 118 widened names and labels to hold UTF-8
+    let (name_len, format_len, label_len) = if release == 117 { (33, 49, 81) } else { (129, 57, 321) };
+    let sort_len = if release == 119 { 4 } else { 2 };
+
+    expect_tag(reader, "</map><variable_types>")?;
+    read_bytes(reader, nvar * 2)?;
+    expect_tag(reader, "</variable_types><varnames>")?;
+    let names = read_bytes(reader, nvar * name_len)?;
+    expect_tag(reader, "</varnames><sortlist>")?;
+    read_bytes(reader, (nvar + 1) * sort_len)?;
+    expect_tag(reader, "</sortlist><formats>")?;
+    read_bytes(reader, nvar * format_len)?;
+    expect_tag(reader, "</formats><value_label_names>")?;
+    let value_label_names = read_bytes(reader, nvar * name_len)?;
+    expect_tag(reader, "</value_label_names><variable_labels>")?;
+    let labels = read_bytes(reader, nvar * label_len)?;
+
+    let mut metadata = stata_variables(nvar, &names, name_len, &value_label_names, name_len, &labels, label_len);
+    metadata.format_version = Some(release.to_string());
+    metadata.case_count = Some(nobs);
+    metadata.file_label = decode_text(&data_label).map(|label| redact_nhs_numbers(&label));
+    metadata.created = decode_text(&timestamp).and_then(|timestamp| stata_timestamp(&timestamp));
+    Ok(metadata)
+}
+
+fn expect_tag<R: Read>(reader: &mut R, tag: &str) -> Result<()> {
+    if read_bytes(reader, tag.len())? != tag.as_bytes() {
+        anyhow::bail!("Expected {} in Stata header", tag);
+    }
+    Ok(())
+}
+
+/// Builds metadata from the fixed-width name, value label name and label arrays.
+/// A variable has value labels when its value label name is set.
+fn stata_variables(
+    nvar: usize,
+    names: &[u8],
+    name_len: usize,
+    value_label_names: &[u8],
+    value_label_name_len: usize,
+    labels: &[u8],
+    label_len: usize,
+) -> StatisticalMetadata {
+    let variables = (0..nvar)
+        .map(|index| Variable {
+            name: decode_text(&names[index * name_len..(index + 1) * name_len]).unwrap_or_default(),
+            label: decode_text(&labels[index * label_len..(index + 1) * label_len]),
+            value_labels: decode_text(
+                &value_label_names[index * value_label_name_len..(index + 1) * value_label_name_len],
+            )
+            .is_some(),
+        })
+        .collect();
+    new_metadata("stata", variables)
+}
+
+/// Converts a Stata timestamp (`dd Mon yyyy hh:mm`) to the format used elsewhere.
+fn stata_timestamp(timestamp: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(timestamp.trim(), "%d %b %Y %H:%M")
+        .ok()
+        .map(format_datetime)
+}
+
+// SAS .sas7bdat: a header followed by pages whose subheaders describe the columns.
+// Offsets follow the reverse-engineered layout used by other open-source readers.
+const SAS_ALIGNMENT_MARKER: u8 = 0x33;
+const SAS_MAX_HEADER_LEN: usize = 1024 * 1024;
+
+const SAS_PAGE_META: u16 = 0x0000;
+const SAS_PAGE_DATA: u16 = 0x0100;
+const SAS_PAGE_MIX: u16 = 0x0200;
+const SAS_PAGE_MIX_ALT: u16 = 0x0280;
+const SAS_PAGE_AMD: u16 = 0x0400;
+const SAS_PAGE_META_ALT: u16 = 0x4000;
+
+// Subheaders flagged as truncated carry no usable content
+const SAS_TRUNCATED_SUBHEADER: u8 = 1;
+
+enum SasSubheader {
+    RowSize,
+    ColumnText,
+    ColumnName,
+    FormatAndLabel,
+    Other,
+}
+
+/// Classifies a subheader by its signature, which fills the first integer of the subheader.
+fn sas_subheader_kind(signature: &[u8]) -> SasSubheader {
+    match signature {
+        [0xF7, 0xF7, 0xF7, 0xF7]
+        | [0x00, 0x00, 0x00, 0x00, 0xF7, 0xF7, 0xF7, 0xF7]
+        | [0xF7, 0xF7, 0xF7, 0xF7, 0x00, 0x00, 0x00, 0x00]
+        | [0xF7, 0xF7, 0xF7, 0xF7, 0xFF, 0xFF, 0xFB, 0xFE] => SasSubheader::RowSize,
+        [0xFD, 0xFF, 0xFF, 0xFF]
+        | [0xFF, 0xFF, 0xFF, 0xFD]
+        | [0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        | [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFD] => SasSubheader::ColumnText,
+        [0xFF, 0xFF, 0xFF, 0xFF] | [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF] => SasSubheader::ColumnName,
+        [0xFE, 0xFB, 0xFF, 0xFF]
+        | [0xFF, 0xFF, 0xFB, 0xFE]
+        | [0xFE, 0xFB, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        | [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFB, 0xFE] => SasSubheader::FormatAndLabel,
+        _ => SasSubheader::Other,
+    }
+}
+
+/// A reference into one of the column text blocks.
+#[derive(Clone, Copy)]
+struct SasTextRef {
+    block: usize,
+    offset: usize,
+    len: usize,
+}
+
+fn sas_metadata<R: Read + Seek>(reader: &mut R) -> Result<StatisticalMetadata> {
+    reader.seek(SeekFrom::Start(0))?;
+    let prefix = read_bytes(reader, 288)?;
+    if prefix[..32] != SAS_MAGIC {
+        anyhow::bail!("Not a SAS7BDAT file");
+    }
+
+    let align1 = if prefix[32] == SAS_ALIGNMENT_MARKER { 4 } else { 0 };
+    let is_64_bit = prefix[35] == SAS_ALIGNMENT_MARKER;
+    let align2 = if is_64_bit { 4 } else { 0 };
+    let int_len = if is_64_bit { 8 } else { 4 };
+    let order = ByteOrder {
+        little_endian: prefix[37] == 0x01,
+    };
+
+    let header_len = order.u32(&prefix[196 + align1..200 + align1]) as usize;
+    let page_len = order.u32(&prefix[200 + align1..204 + align1]) as usize;
+    let page_count = order.u32(&prefix[204 + align1..208 + align1]) as usize;
+    if !(288..=SAS_MAX_HEADER_LEN).contains(&header_len) || page_len == 0 || page_len > MAX_RECORD_SIZE {
+        anyhow::bail!("Corrupt SAS7BDAT header");
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    let header = read_bytes(reader, header_len)?;
+    let release_offset = 216 + align1 + align2;
+    let release = decode_text(header.get(release_offset..release_offset + 8).unwrap_or_default());
+    let host = decode_text(header.get(release_offset + 8..release_offset + 24).unwrap_or_default());
+
+    let read_uint = |bytes: &[u8], offset: usize| -> Option<u64> {
+        let field = bytes.get(offset..offset + int_len)?;
+        Some(if is_64_bit {
+            order.u64(field)
+        } else {
+            order.u32(field) as u64
+        })
+    };
+
+    let page_bit_offset = if is_64_bit { 32 } else { 16 };
+    let pointer_len = if is_64_bit { 24 } else { 12 };
+
+    let mut row_count = None;
+    let mut text_blocks: Vec<Vec<u8>> = Vec::new();
+    let mut names: Vec<SasTextRef> = Vec::new();
+    let mut labels: Vec<Option<SasTextRef>> = Vec::new();
+
+    // Metadata pages come first; the first data or mixed page ends the dictionary
+    'pages: for page_index in 0..page_count {
+        reader.seek(SeekFrom::Start((header_len + page_index * page_len) as u64))?;
+        let page = read_bytes(reader, page_len)?;
+        let Some(page_type) = page.get(page_bit_offset..page_bit_offset + 2).map(|bytes| order.u16(bytes)) else {
+            break;
+        };
+
+        let is_mix = page_type == SAS_PAGE_MIX || page_type == SAS_PAGE_MIX_ALT;
+        match page_type {
+            SAS_PAGE_DATA => break,
+            SAS_PAGE_META | SAS_PAGE_META_ALT | SAS_PAGE_AMD => {}
+            _ if is_mix => {}
+            _ => continue,
+        }
+
+        let Some(subheader_count) = page.get(page_bit_offset + 4..page_bit_offset + 6).map(|bytes| order.u16(bytes)) else {
+            continue;
+        };
+        for index in 0..subheader_count as usize {
+            let pointer = page_bit_offset + 8 + index * pointer_len;
+            let (Some(offset), Some(len)) = (read_uint(&page, pointer), read_uint(&page, pointer + int_len)) else {
+                break 'pages;
+            };
+            let (offset, len) = (offset as usize, len as usize);
+            let compression = page.get(pointer + 2 * int_len).copied().unwrap_or(0);
+            if len == 0 || compression == SAS_TRUNCATED_SUBHEADER {
+                continue;
+            }
+            let Some(subheader) = offset.checked_add(len).and_then(|end| page.get(offset..end)) else {
+                continue;
+            };
+            let Some(signature) = subheader.get(..int_len) else {
+                continue;
+            };
+
+            match sas_subheader_kind(signature) {
+                SasSubheader::RowSize => row_count = read_uint(subheader, 6 * int_len),
+                SasSubheader::ColumnText => {
+                    // The block starts after the signature with its own 2-byte size
+                    let block = &subheader[int_len..];
+                    let size = block.get(..2).map(|bytes| order.u16(bytes) as usize).unwrap_or(0);
+                    text_blocks.push(block[..size.min(block.len())].to_vec());
+                }
+                SasSubheader::ColumnName => {
+                    // 8-byte entries of (text block, offset, length), after a 12-byte preamble
+                    let entries = len.saturating_sub(2 * int_len + 12) / 8;
+                    for entry in 0..entries {
+                        let base = int_len + 8 * (entry + 1);
+                        if let Some(field) = subheader.get(base..base + 6) {
+                            names.push(SasTextRef {
+                                block: order.u16(&field[0..2]) as usize,
+                                offset: order.u16(&field[2..4]) as usize,
+                                len: order.u16(&field[4..6]) as usize,
+                            });
+                        }
+                    }
+                }
+                SasSubheader::FormatAndLabel => {
+                    let base = 3 * int_len;
+                    labels.push(subheader.get(base + 28..base + 34).map(|field| SasTextRef {
+                        block: order.u16(&field[0..2]) as usize,
+                        offset: order.u16(&field[2..4]) as usize,
+                        len: order.u16(&field[4..6]) as usize,
+                    }));
+                }
+                SasSubheader::Other => {}
+            }
+        }
+
+        if is_mix {
+            break;
+        }
+    }
+
+    let resolve = |text: &SasTextRef| {
+        text_blocks
+            .get(text.block)
+            .and_then(|block| block.get(text.offset..text.offset + text.len))
+            .and_then(decode_text)
+    };
+
+    let variables = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| Variable {
+            name: resolve(name).unwrap_or_default(),
+            label: labels.get(index).copied().flatten().filter(|label| label.len > 0).and_then(|label| resolve(&label)),
+            // SAS keeps value labels (formats) in separate catalog files
+            value_labels: false,
+        })
+        .collect();
+
+    let sas_date = |offset: usize| {
+        let seconds = order.f64(header.get(offset..offset + 8)?);
+        if !seconds.is_finite() {
+            return None;
+        }
+        let epoch = NaiveDate::from_ymd_opt(1960, 1, 1)?.and_hms_opt(0, 0, 0)?;
+        epoch.checked_add_signed(Duration::try_seconds(seconds as i64)?).map(format_datetime)
+    };
+
+    let mut metadata = new_metadata("sas", variables);
+    metadata.format_version = Some(if is_64_bit { "64-bit" } else { "32-bit" }.to_string());
+    metadata.creator = match (release, host) {
+        (Some(release), Some(host)) => Some(format!("SAS {} ({})", release, host)),
+        (Some(release), None) => Some(format!("SAS {}", release)),
+        _ => None,
+    };
+    metadata.created = sas_date(164 + align1);
+    metadata.modified = sas_date(172 + align1);
+    metadata.case_count = row_count;
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn extract(bytes: Vec<u8>, format: &str) -> Result<StatisticalMetadata> {
+        extract_statistical_metadata(&mut Cursor::new(bytes), format)
+    }
+
+    /// A 32-bit little-endian SAS header of `header_len` bytes, followed by `pages`.
+    fn sas_file(header_len: usize, page_len: usize, pages: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![0u8; header_len];
+        bytes[..32].copy_from_slice(&SAS_MAGIC);
+        bytes[37] = 0x01;
+        bytes[196..200].copy_from_slice(&(header_len as u32).to_le_bytes());
+        bytes[200..204].copy_from_slice(&(page_len as u32).to_le_bytes());
+        bytes[204..208].copy_from_slice(&(pages.len() as u32).to_le_bytes());
+        for page in pages {
+            bytes.extend(page);
+        }
+        bytes
+    }
+
+    fn padded(text: &str, len: usize) -> Vec<u8> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(len, 0);
+        bytes
+    }
+
+    fn spss_variable(name: &str, label: Option<&str>) -> Vec<u8> {
+        let mut record = Vec::new();
+        for field in [SPSS_VARIABLE_RECORD, 0, i32::from(label.is_some()), 0, 0x050800, 0x050800] {
+            record.extend(field.to_le_bytes());
+        }
+        record.extend(padded(name, 8));
+        if let Some(label) = label {
+            record.extend((label.len() as i32).to_le_bytes());
+            record.extend(padded(label, label.len().div_ceil(4) * 4));
+        }
+        record
+    }
+
+    /// A little-endian SPSS file with three numeric variables: a labelled one with a long
+    /// name, one with value labels, and a plain one.
+    fn spss_file() -> Vec<u8> {
+        let mut bytes = b"$FL2".to_vec();
+        bytes.extend(padded("@(#) IBM SPSS STATISTICS 27.0", 60));
+        bytes.extend(2i32.to_le_bytes());
+        bytes.extend([3i32, 0, 0, 250].iter().flat_map(|field| field.to_le_bytes()));
+        bytes.extend(100f64.to_le_bytes());
+        bytes.extend(b"01 Mar 2409:30:00");
+        bytes.extend(padded("Admissions extract", 64));
+        bytes.extend([0; 3]);
+        assert_eq!(bytes.len(), SPSS_HEADER_LEN);
+
+        bytes.extend(spss_variable("AGE", Some("Age at admission")));
+        bytes.extend(spss_variable("SEX", None));
+        bytes.extend(spss_variable("WARD", None));
+
+        // One value label, attached to the second slot
+        bytes.extend(SPSS_VALUE_LABEL_RECORD.to_le_bytes());
+        bytes.extend(1i32.to_le_bytes());
+        bytes.extend(1f64.to_le_bytes());
+        bytes.push(4);
+        bytes.extend(padded("Male", 7));
+        bytes.extend(SPSS_VALUE_LABEL_VARIABLES_RECORD.to_le_bytes());
+        bytes.extend(1i32.to_le_bytes());
+        bytes.extend(2i32.to_le_bytes());
+
+        let long_names = b"AGE=age_at_admission\tSEX=sex";
+        for field in [SPSS_EXTENSION_RECORD, SPSS_LONG_NAMES_SUBTYPE, 1, long_names.len() as i32] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(long_names);
+        bytes.extend(SPSS_DICTIONARY_END.to_le_bytes());
+        bytes.extend(0i32.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn spss_dictionary() {
+        let metadata = extract(spss_file(), "spss").unwrap();
+        assert_eq!(metadata.format_version.as_deref(), Some("$FL2"));
+        assert_eq!(metadata.creator.as_deref(), Some("IBM SPSS STATISTICS 27.0"));
+        assert_eq!(metadata.created.as_deref(), Some("2024-03-01T09:30"));
+        assert_eq!(metadata.file_label.as_deref(), Some("Admissions extract"));
+        assert_eq!(metadata.case_count, Some(250));
+        assert_eq!(metadata.columns, ["age_at_admission", "sex", "WARD"]);
+        assert_eq!(metadata.variable_labels, [Some("Age at admission".to_string()), None, None]);
+        assert_eq!(metadata.value_labelled_columns, ["sex"]);
+
+        // A record length running past the end of the file is an error, not an allocation
+        let mut truncated = spss_file();
+        truncated.truncate(SPSS_HEADER_LEN + 4 + 20 + 8 + 4);
+        assert!(extract(truncated, "spss").is_err());
+    }
+
+    /// A Stata 13 (release 117) header with two variables, the second value-labelled.
+    fn stata_file() -> Vec<u8> {
+        let mut bytes = b"<stata_dta><header><release>117</release><byteorder>LSF</byteorder><K>".to_vec();
+        bytes.extend(2u16.to_le_bytes());
+        bytes.extend(b"</K><N>");
+        bytes.extend(1234u32.to_le_bytes());
+        bytes.extend(b"</N><label>");
+        bytes.push(9);
+        bytes.extend(b"Referrals");
+        bytes.extend(b"</label><timestamp>");
+        bytes.push(17);
+        bytes.extend(b"01 Mar 2024 09:30");
+        bytes.extend(b"</timestamp></header><map>");
+        bytes.extend([0; 14 * 8]);
+        bytes.extend(b"</map><variable_types>");
+        bytes.extend([0; 2 * 2]);
+        bytes.extend(b"</variable_types><varnames>");
+        bytes.extend(padded("patient_id", 33));
+        bytes.extend(padded("outcome", 33));
+        bytes.extend(b"</varnames><sortlist>");
+        bytes.extend([0; 3 * 2]);
+        bytes.extend(b"</sortlist><formats>");
+        bytes.extend([0; 2 * 49]);
+        bytes.extend(b"</formats><value_label_names>");
+        bytes.extend(padded("", 33));
+        bytes.extend(padded("outcome_lbl", 33));
+        bytes.extend(b"</value_label_names><variable_labels>");
+        bytes.extend(padded("Patient identifier", 81));
+        bytes.extend(padded("", 81));
+        bytes
+    }
+
+    #[test]
+    fn stata_tagged_header() {
+        let metadata = extract(stata_file(), "stata").unwrap();
+        assert_eq!(metadata.format_version.as_deref(), Some("117"));
+        assert_eq!(metadata.case_count, Some(1234));
+        assert_eq!(metadata.file_label.as_deref(), Some("Referrals"));
+        assert_eq!(metadata.created.as_deref(), Some("2024-03-01T09:30"));
+        assert_eq!(metadata.columns, ["patient_id", "outcome"]);
+        assert_eq!(metadata.variable_labels, [Some("Patient identifier".to_string()), None]);
+        assert_eq!(metadata.value_labelled_columns, ["outcome"]);
+    }
+
+    #[test]
+    fn sas_short_pages_and_dates_out_of_range() {
+        // The page is too short to hold a subheader count, and the dates are out of range
+        let mut sas = sas_file(1024, 18, &[vec![0u8; 18]]);
+        sas[164..172].copy_from_slice(&1e300f64.to_le_bytes());
+        sas[172..180].copy_from_slice(&(-1e300f64).to_le_bytes());
+        let metadata = extract(sas, "sas").unwrap();
+        assert!(metadata.columns.is_empty());
+        assert_eq!(metadata.created, None);
+        assert_eq!(metadata.modified, None);
+
+        // A subheader pointer whose offset and length overflow
+        let mut page = vec![0u8; 64];
+        page[20..22].copy_from_slice(&1u16.to_le_bytes());
+        page[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        page[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(extract(sas_file(1024, 64, &[page]), "sas").is_ok());
+    }
+}
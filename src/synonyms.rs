@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A user-supplied dictionary of column name synonyms and abbreviations. Keys and values
+/// are held in the normalised form used by the column similarity hash (lowercase,
+/// alphanumeric characters only).
+#[derive(Debug, Default)]
+pub(crate) struct ColumnSynonyms {
+    canonical: HashMap<String, String>,
+}
+
+impl ColumnSynonyms {
+    /// Loads a dictionary with one mapping per line, `alias -> canonical`. Several aliases
+    /// may share a line (`dob, birth date -> date of birth`); blank lines and lines
+    /// starting with `#` are ignored.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).context(format!("Failed to read synonym dictionary: {:?}", path))?;
+        Self::parse(&text, path)
+    }
+
+    /// Parses dictionary text; `path` is only used in error messages.
+    pub(crate) fn parse(text: &str, path: &Path) -> Result<Self> {
+        let mut synonyms = ColumnSynonyms::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((aliases, canonical)) = line.split_once("->") else {
+                anyhow::bail!("{:?} line {}: expected `alias -> canonical`", path, number + 1);
+            };
+            let canonical = normalise(canonical);
+            if canonical.is_empty() {
+                anyhow::bail!("{:?} line {}: missing canonical name", path, number + 1);
+            }
+            for alias in aliases.split(',').map(normalise) {
+                if alias.is_empty() {
+                    anyhow::bail!("{:?} line {}: missing alias", path, number + 1);
+                }
+                synonyms.canonical.insert(alias, canonical.clone());
+            }
+        }
+
+        Ok(synonyms)
+    }
+
+    /// Returns the canonical form of a column name. A name matching an alias as a whole is
+    /// replaced outright (`DOB`); otherwise each word is looked up on its own, so
+    /// abbreviations expand inside longer names (`Pt_ID` to `patientid`), and the
+    /// expanded name is looked up once more. Mappings are not applied repeatedly.
+    pub(crate) fn canonical_name(&self, column: &str) -> String {
+        let words = split_words(column);
+        let whole = words.concat();
+        if let Some(canonical) = self.canonical.get(&whole) {
+            return canonical.clone();
+        }
+
+        let expanded: String = words
+            .iter()
+            .map(|word| self.canonical.get(word).unwrap_or(word).as_str())
+            .collect();
+        self.canonical.get(&expanded).cloned().unwrap_or(expanded)
+    }
+}
+
+fn normalise(name: &str) -> String {
+    split_words(name).concat()
+}
+
+/// Splits a column name into lowercase words at punctuation, spaces and case changes:
+/// `patient_id`, `Patient ID`, `PatientId` and `PATIENTId` all give `patient`, `id`.
+fn split_words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+
+        if c.is_uppercase() && !word.is_empty() {
+            let previous = chars[index - 1];
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            // "patientId" breaks before the I; "PATIENTId" breaks before the I too
+            if !previous.is_uppercase() || next_is_lower {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<ColumnSynonyms> {
+        ColumnSynonyms::parse(text, Path::new("synonyms.txt"))
+    }
+
+    #[test]
+    fn names_split_at_punctuation_and_case_changes() {
+        for name in ["patient_id", "Patient ID", "PatientId", "patientId", "PATIENTId", "PATIENT-ID"] {
+            assert_eq!(split_words(name), ["patient", "id"], "{}", name);
+        }
+        assert_eq!(split_words("NHSNumber2"), ["nhs", "number2"]);
+        assert!(split_words(" _ ").is_empty());
+    }
+
+    #[test]
+    fn aliases_map_whole_names_and_words() {
+        let synonyms = parse(
+            "# Abbreviations\n\
+             \n\
+             pt -> patient\n\
+             DOB, Birth_Date -> date of birth\n\
+             patient id -> patient identifier\n",
+        )
+        .unwrap();
+
+        assert_eq!(synonyms.canonical_name("DOB"), "dateofbirth");
+        assert_eq!(synonyms.canonical_name("birthDate"), "dateofbirth");
+        // The alias is expanded inside the name, then the expanded name is looked up
+        assert_eq!(synonyms.canonical_name("Pt_ID"), "patientidentifier");
+        assert_eq!(synonyms.canonical_name("Pt_Name"), "patientname");
+        assert_eq!(synonyms.canonical_name("Postcode"), "postcode");
+
+        let abbreviations = parse("pt -> patient").unwrap();
+        assert_eq!(abbreviations.canonical_name("Pt_ID"), "patientid");
+    }
+
+    #[test]
+    fn lines_without_an_alias_or_canonical_name_are_errors() {
+        for (text, message) in [
+            ("pt -> patient\ndob date of birth", "line 2: expected `alias -> canonical`"),
+            ("pt -> ", "line 1: missing canonical name"),
+            (" -> patient", "line 1: missing alias"),
+            ("dob, , birth date -> date of birth", "line 1: missing alias"),
+        ] {
+            let error = parse(text).unwrap_err().to_string();
+            assert!(error.ends_with(message), "{}", error);
+        }
+    }
+}